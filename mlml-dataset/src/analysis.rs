@@ -0,0 +1,549 @@
+use std::fmt;
+
+use crate::{
+    expr::{BinaryOp, BinaryOpType, Expr},
+    generator::variable_names,
+    sample::Assignment,
+};
+
+/// The most variables a truth table may be built for; the table has 2^n rows.
+pub const MAX_TRUTH_TABLE_VARIABLES: usize = 20;
+
+/// The most variables whose orders are all tried when looking for the canonical truth table up to
+/// renaming; there are n! of them.
+pub const MAX_PERMUTED_VARIABLES: usize = 6;
+
+/// The semantic status of an expression across all the possible assignments.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Validity {
+    /// True under every assignment.
+    Tautology,
+    /// False under every assignment.
+    Contradiction,
+    /// True under some assignments and false under others.
+    Contingent,
+}
+
+impl Validity {
+    pub fn is_satisfiable(&self) -> bool {
+        *self != Validity::Contradiction
+    }
+}
+
+impl fmt::Display for Validity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Validity::Tautology => write!(f, "tautology"),
+            Validity::Contradiction => write!(f, "contradiction"),
+            Validity::Contingent => write!(f, "contingent"),
+        }
+    }
+}
+
+/// The results of evaluating an expression under every assignment of its variables.
+///
+/// Rows are ordered like binary numbers over the variables (in the order of their first
+/// occurrence), with the first variable being the most significant one; row 0 assigns false to
+/// every variable.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct TruthTable {
+    variables: Vec<String>,
+    results: Vec<bool>,
+}
+
+impl TruthTable {
+    pub fn new(expr: &Expr) -> Self {
+        Self::with_variables(
+            expr,
+            expr.variables().into_iter().map(String::from).collect(),
+        )
+    }
+
+    /// Builds the truth table of the function the expression denotes, independently of how it is
+    /// written: the variables are sorted by name, and the ones the result doesn't depend on (as
+    /// `b` in `a ∨ (b ∧ ¬b)`) are left out. Equivalent expressions have equal canonical tables.
+    ///
+    /// Returns `None` if the expression has more than [`MAX_TRUTH_TABLE_VARIABLES`] variables.
+    pub fn canonical(expr: &Expr) -> Option<Self> {
+        let mut variables: Vec<String> = expr.variables().into_iter().map(String::from).collect();
+        if variables.len() > MAX_TRUTH_TABLE_VARIABLES {
+            return None;
+        }
+        variables.sort();
+
+        let mut table = Self::with_variables(expr, variables);
+        let mut j = 0;
+        while j < table.variables.len() {
+            if table.is_essential(j) {
+                j += 1;
+            } else {
+                table.remove_variable(j);
+            }
+        }
+
+        Some(table)
+    }
+
+    /// Builds the canonical truth table (see [`TruthTable::canonical`]) of the function the
+    /// expression denotes up to a renaming of its variables: they are named `a`, `b`, `c`, ... in
+    /// the order that makes the results the smallest, so expressions that are equivalent once
+    /// their variables are renamed have equal tables.
+    ///
+    /// With more than [`MAX_PERMUTED_VARIABLES`] essential variables, they are ordered by their
+    /// first occurrence instead, which only identifies some of the renamings. Returns `None` if the
+    /// expression has more than [`MAX_TRUTH_TABLE_VARIABLES`] variables.
+    pub fn canonical_up_to_renaming(expr: &Expr) -> Option<Self> {
+        let mut table = Self::canonical(&expr.canonical_renaming())?;
+        let n = table.variables.len();
+        if n <= MAX_PERMUTED_VARIABLES {
+            table.results = permutations(n)
+                .into_iter()
+                .map(|order| table.reordered_results(&order))
+                .min()
+                .unwrap();
+        }
+        table.variables = variable_names(n);
+
+        Some(table)
+    }
+
+    /// The results with the variables reordered, the j-th one becoming `order[j]`.
+    fn reordered_results(&self, order: &[usize]) -> Vec<bool> {
+        let n = self.variables.len();
+        (0..self.results.len())
+            .map(|row| {
+                let old_row = (0..n)
+                    .filter(|j| Self::value_in_row(row, *j, n))
+                    .fold(0, |old_row, j| old_row | 1 << (n - 1 - order[j]));
+                self.results[old_row]
+            })
+            .collect()
+    }
+
+    /// Builds the truth table over the given variables, which must include all the variables of
+    /// the expression.
+    fn with_variables(expr: &Expr, variables: Vec<String>) -> Self {
+        assert!(
+            variables.len() <= MAX_TRUTH_TABLE_VARIABLES,
+            "too many variables for a truth table: {}",
+            variables.len()
+        );
+
+        let mut state: Vec<(String, bool)> = variables.iter().map(|v| (v.clone(), false)).collect();
+        let results = (0..1usize << variables.len())
+            .map(|row| {
+                for (j, (_, value)) in state.iter_mut().enumerate() {
+                    *value = Self::value_in_row(row, j, variables.len());
+                }
+                expr.evaluate(&state)
+            })
+            .collect();
+
+        Self { variables, results }
+    }
+
+    fn value_in_row(row: usize, var_idx: usize, num_vars: usize) -> bool {
+        (row >> (num_vars - 1 - var_idx)) & 1 == 1
+    }
+
+    /// Whether flipping the given variable changes the result in some row.
+    fn is_essential(&self, var_idx: usize) -> bool {
+        let bit = 1 << (self.variables.len() - 1 - var_idx);
+        (0..self.results.len())
+            .any(|row| row & bit == 0 && self.results[row] != self.results[row | bit])
+    }
+
+    /// Removes a variable the result doesn't depend on, keeping the rows in which it is false.
+    fn remove_variable(&mut self, var_idx: usize) {
+        let bit = 1 << (self.variables.len() - 1 - var_idx);
+        self.results = (0..self.results.len())
+            .filter(|row| row & bit == 0)
+            .map(|row| self.results[row])
+            .collect();
+        self.variables.remove(var_idx);
+    }
+
+    pub fn variables(&self) -> &[String] {
+        &self.variables
+    }
+
+    /// The result of the expression in each row.
+    pub fn results(&self) -> &[bool] {
+        &self.results
+    }
+
+    /// Packs the number of variables and the results, 8 rows per byte, into a compact key; tables
+    /// over the same variables are equal if and only if their keys are.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = vec![self.variables.len() as u8];
+        bytes.extend(self.results.chunks(8).map(|rows| {
+            rows.iter()
+                .enumerate()
+                .fold(0u8, |byte, (i, result)| byte | (*result as u8) << i)
+        }));
+
+        bytes
+    }
+
+    /// Returns the assignment corresponding to the given row.
+    pub fn assignment(&self, row: usize) -> Assignment {
+        let n = self.variables.len();
+        let bindings = self
+            .variables
+            .iter()
+            .enumerate()
+            .map(|(j, v)| (v.clone(), Self::value_in_row(row, j, n)));
+
+        Assignment::new(bindings).unwrap()
+    }
+
+    /// Iterates over all the rows as (assignment, result) pairs.
+    pub fn rows(&self) -> impl Iterator<Item = (Assignment, bool)> + '_ {
+        self.results
+            .iter()
+            .enumerate()
+            .map(|(row, result)| (self.assignment(row), *result))
+    }
+
+    pub fn validity(&self) -> Validity {
+        match self.num_models() {
+            0 => Validity::Contradiction,
+            n if n == self.results.len() => Validity::Tautology,
+            _ => Validity::Contingent,
+        }
+    }
+
+    /// The number of satisfying assignments.
+    pub fn num_models(&self) -> usize {
+        self.results.iter().filter(|r| **r).count()
+    }
+
+    /// Returns the first assignment under which the expression is true, if there is one.
+    pub fn model(&self) -> Option<Assignment> {
+        self.results
+            .iter()
+            .position(|r| *r)
+            .map(|row| self.assignment(row))
+    }
+
+    /// Returns the first assignment under which the expression is false, if there is one.
+    pub fn counter_model(&self) -> Option<Assignment> {
+        self.results
+            .iter()
+            .position(|r| !*r)
+            .map(|row| self.assignment(row))
+    }
+
+    /// Returns the disjunction of the rows in which the result is true, each one written as the
+    /// conjunction of its literals; this is ⊥ if there are no such rows and ⊤ if there are no
+    /// variables.
+    pub fn to_dnf(&self) -> Expr {
+        let minterms = self
+            .results
+            .iter()
+            .enumerate()
+            .filter(|(_, result)| **result)
+            .map(|(row, _)| {
+                let literals = self
+                    .variables
+                    .iter()
+                    .enumerate()
+                    .map(|(j, v)| {
+                        let var = Expr::Var(v.clone());
+                        if Self::value_in_row(row, j, self.variables.len()) {
+                            var
+                        } else {
+                            Expr::Not(Box::new(var))
+                        }
+                    })
+                    .collect();
+                Expr::conjunction(literals)
+            })
+            .collect();
+
+        Expr::disjunction(minterms)
+    }
+}
+
+impl Expr {
+    /// Whether the two expressions have the same result under every assignment of their
+    /// variables.
+    ///
+    /// The truth tables are compared when there are few enough variables; otherwise the
+    /// expressions are equivalent if their exclusive disjunction is unsatisfiable, which is
+    /// decided by a SAT solver.
+    pub fn is_equivalent(&self, other: &Expr) -> bool {
+        let mut variables: Vec<String> = self.variables().into_iter().map(String::from).collect();
+        for v in other.variables() {
+            if !variables.iter().any(|v2| v2 == v) {
+                variables.push(v.to_owned());
+            }
+        }
+
+        if variables.len() <= MAX_TRUTH_TABLE_VARIABLES {
+            TruthTable::with_variables(self, variables.clone()).results
+                == TruthTable::with_variables(other, variables).results
+        } else {
+            let difference = Expr::BinaryOp(Box::new(BinaryOp::new(
+                BinaryOpType::Xor,
+                self.clone(),
+                other.clone(),
+            )));
+            !is_satisfiable_dpll(&difference)
+        }
+    }
+
+    /// Whether the expression is a tautology, a contradiction, or contingent.
+    ///
+    /// The truth table is used when there are few enough variables; otherwise the expression and
+    /// its negation are checked for satisfiability by a SAT solver.
+    pub fn validity(&self) -> Validity {
+        if self.variables().len() <= MAX_TRUTH_TABLE_VARIABLES {
+            return TruthTable::new(self).validity();
+        }
+
+        if !is_satisfiable_dpll(self) {
+            Validity::Contradiction
+        } else if !is_satisfiable_dpll(&Expr::Not(Box::new(self.clone()))) {
+            Validity::Tautology
+        } else {
+            Validity::Contingent
+        }
+    }
+
+    /// Returns the canonical form of the expression: the DNF of its canonical truth table (see
+    /// [`TruthTable::canonical`]). Two expressions are equivalent if and only if their canonical
+    /// forms are equal.
+    ///
+    /// Returns `None` if the expression has too many variables for a truth table.
+    pub fn canonical_form(&self) -> Option<Expr> {
+        TruthTable::canonical(self).map(|table| table.to_dnf())
+    }
+}
+
+/// Returns all the orders of `0..n`.
+fn permutations(n: usize) -> Vec<Vec<usize>> {
+    if n == 0 {
+        return vec![vec![]];
+    }
+
+    permutations(n - 1)
+        .into_iter()
+        .flat_map(|order| {
+            (0..n).map(move |i| {
+                let mut order = order.clone();
+                order.insert(i, n - 1);
+                order
+            })
+        })
+        .collect()
+}
+
+/// A literal of a clause: a variable index and whether the variable appears unnegated.
+type Literal = (usize, bool);
+
+/// Decides satisfiability with the DPLL algorithm on the Tseitin encoding of the expression,
+/// without enumerating all the assignments.
+fn is_satisfiable_dpll(expr: &Expr) -> bool {
+    // the variables of the expression come first, as they are the only ones branched on: the
+    // variables introduced by the encoding are determined by them through unit propagation
+    let mut names: Vec<String> = expr.variables().into_iter().map(String::from).collect();
+    let num_branching = names.len();
+    let mut literal = |lit: &Expr| {
+        let (name, polarity) = match lit {
+            Expr::Var(v) => (v, true),
+            Expr::Not(e) => match &**e {
+                Expr::Var(v) => (v, false),
+                _ => unreachable!(),
+            },
+            _ => unreachable!(),
+        };
+        let idx = names.iter().position(|n| n == name).unwrap_or_else(|| {
+            names.push(name.clone());
+            names.len() - 1
+        });
+        (idx, polarity)
+    };
+    let clauses: Vec<Vec<Literal>> = expr
+        .tseitin_clauses()
+        .iter()
+        .map(|clause| clause.iter().map(&mut literal).collect())
+        .collect();
+
+    let mut values = vec![None; names.len()];
+    dpll(&clauses, &mut values, num_branching)
+}
+
+/// Searches for a satisfying extension of `values`, branching on the first `num_branching`
+/// variables.
+fn dpll(clauses: &[Vec<Literal>], values: &mut [Option<bool>], num_branching: usize) -> bool {
+    // unit propagation: a clause with a single unassigned literal and no true one forces it
+    let mut propagated = Vec::new();
+    let mut conflict = false;
+    loop {
+        let mut unit = None;
+        for clause in clauses {
+            if clause.iter().any(|(v, p)| values[*v] == Some(*p)) {
+                continue;
+            }
+            let mut unassigned = clause.iter().filter(|(v, _)| values[*v].is_none());
+            match (unassigned.next(), unassigned.next()) {
+                (None, _) => conflict = true,
+                (Some(lit), None) => unit = Some(*lit),
+                _ => continue,
+            }
+            break;
+        }
+
+        match unit {
+            Some((v, p)) if !conflict => {
+                values[v] = Some(p);
+                propagated.push(v);
+            }
+            _ => break,
+        }
+    }
+
+    let satisfiable = !conflict
+        && match values[..num_branching].iter().position(Option::is_none) {
+            None => clauses
+                .iter()
+                .all(|clause| clause.iter().any(|(v, p)| values[*v] == Some(*p))),
+            Some(v) => [true, false].into_iter().any(|b| {
+                values[v] = Some(b);
+                let satisfiable = dpll(clauses, values, num_branching);
+                values[v] = None;
+                satisfiable
+            }),
+        };
+
+    if !satisfiable {
+        for v in propagated {
+            values[v] = None;
+        }
+    }
+
+    satisfiable
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::SeedableRng;
+    use rand_xorshift::XorShiftRng;
+
+    use super::*;
+    use crate::{
+        generator::{ExprGenerator, variable_names},
+        parser::Parser,
+    };
+
+    fn table(input: &str) -> TruthTable {
+        TruthTable::new(&Parser::new(input).parse().unwrap())
+    }
+
+    #[test]
+    fn validity() {
+        assert_eq!(table("p ∨ ¬p").validity(), Validity::Tautology);
+        assert_eq!(table("(p → q) ↔ (¬q → ¬p)").validity(), Validity::Tautology);
+        assert_eq!(table("p ∧ ¬p").validity(), Validity::Contradiction);
+        assert_eq!(table("⊥").validity(), Validity::Contradiction);
+        assert_eq!(table("p → q").validity(), Validity::Contingent);
+    }
+
+    #[test]
+    fn models() {
+        let expr = Parser::new("a ∧ (b ∨ c)").parse().unwrap();
+        let t = TruthTable::new(&expr);
+        assert_eq!(t.variables(), ["a", "b", "c"]);
+        assert_eq!(
+            t.results(),
+            [false, false, false, false, false, true, true, true]
+        );
+        assert_eq!(t.num_models(), 3);
+        assert_eq!(t.model().unwrap().to_string(), "[a, c: true; b: false]");
+        assert_eq!(t.counter_model().unwrap().to_string(), "[a, b, c: false]");
+        assert!(
+            t.rows()
+                .all(|(state, result)| expr.evaluate(state.bindings()) == result)
+        );
+
+        assert_eq!(table("p ∨ ¬p").counter_model(), None);
+        assert_eq!(table("p ∧ ¬p").model(), None);
+    }
+
+    #[test]
+    fn equivalence() {
+        let parse = |input: &str| Parser::new(input).parse().unwrap();
+        let equivalent = |a: &str, b: &str| parse(a).is_equivalent(&parse(b));
+
+        assert!(equivalent("p → q", "¬q → ¬p"));
+        assert!(equivalent("¬(p ∧ q)", "p ↑ q"));
+        assert!(equivalent("a ∨ (b ∧ ¬b)", "a"));
+        assert!(!equivalent("p → q", "q → p"));
+        assert!(!equivalent("a", "b"));
+
+        assert_eq!(parse("a ∨ (b ∧ ¬b)").canonical_form(), Some(parse("a")));
+        assert_eq!(
+            parse("(b → a) ∧ (a → b)").canonical_form(),
+            parse("a ↔ b").canonical_form()
+        );
+        assert_eq!(parse("c ∨ ¬c").canonical_form(), Some(parse("⊤")));
+        assert_eq!(parse("c ∧ ¬c").canonical_form(), Some(parse("⊥")));
+
+        let renamed = |a: &str, b: &str| {
+            TruthTable::canonical_up_to_renaming(&parse(a))
+                == TruthTable::canonical_up_to_renaming(&parse(b))
+        };
+        assert!(renamed("a ∧ b", "c ∧ d"));
+        assert!(renamed("a → b", "¬d ∨ c"));
+        assert!(renamed("(a ∧ ¬b) ∨ (c ∧ x)", "(y ∧ b) ∨ (¬q ∧ z)"));
+        assert!(!renamed("a → b", "a ∧ b"));
+        assert!(!renamed("a ∧ b", "a ∧ b ∧ c"));
+
+        let bytes = |input: &str| TruthTable::canonical(&parse(input)).unwrap().to_bytes();
+        assert_eq!(bytes("a ∧ b"), [2, 0b1000]);
+        assert_eq!(bytes("¬a"), [1, 0b01]);
+        assert_eq!(bytes("⊤"), [0, 0b1]);
+        assert_ne!(bytes("(a ∨ b) ∧ c"), bytes("(a ∨ b) ∧ (c ∨ d)"));
+
+        // too many variables for a truth table
+        let vars: Vec<Expr> = variable_names(MAX_TRUTH_TABLE_VARIABLES + 2)
+            .into_iter()
+            .map(Expr::Var)
+            .collect();
+        let negated = |es: &[Expr]| es.iter().map(|e| Expr::Not(Box::new(e.clone()))).collect();
+        let nand = Expr::Not(Box::new(Expr::conjunction(vars.clone())));
+        assert!(nand.is_equivalent(&Expr::disjunction(negated(&vars))));
+        assert!(!nand.is_equivalent(&Expr::disjunction(negated(&vars[1..]))));
+        assert!(!nand.is_equivalent(&Expr::conjunction(negated(&vars))));
+        assert_eq!(nand.canonical_form(), None);
+
+        let disjunction = Expr::disjunction(vars.clone());
+        assert_eq!(disjunction.validity(), Validity::Contingent);
+        let excluded_middle =
+            Expr::disjunction(vec![disjunction.clone(), negated(&vars[..1])[0].clone()]);
+        assert_eq!(excluded_middle.validity(), Validity::Tautology);
+        assert_eq!(
+            Expr::Not(Box::new(excluded_middle)).validity(),
+            Validity::Contradiction
+        );
+    }
+
+    #[test]
+    fn dpll() {
+        let mut rng = XorShiftRng::seed_from_u64(5);
+        let generator = ExprGenerator::new(4, 4)
+            .with_const_probability(0.1)
+            .with_max_arity(3);
+        let range = variable_names(4);
+
+        for _ in 0..300 {
+            let expr = generator.generate(&range, &mut rng);
+            assert_eq!(
+                is_satisfiable_dpll(&expr),
+                TruthTable::new(&expr).validity().is_satisfiable(),
+                "{expr}"
+            );
+        }
+    }
+}
@@ -1,10 +1,17 @@
 use std::{collections::HashMap, fmt};
 
+use crate::generator::variable_names;
+
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum Expr {
-    Var(char),
+    Var(String),
+    Const(bool),
     Not(Box<Expr>),
     BinaryOp(Box<BinaryOp>),
+    /// A conjunction of at least three operands; use `Expr::conjunction` to construct it.
+    And(Vec<Expr>),
+    /// A disjunction of at least three operands; use `Expr::disjunction` to construct it.
+    Or(Vec<Expr>),
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
@@ -13,6 +20,9 @@ pub enum BinaryOpType {
     Or,
     Implies,
     Equivalent,
+    Xor,
+    Nand,
+    Nor,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
@@ -22,34 +32,223 @@ pub struct BinaryOp {
     pub r: Expr,
 }
 
+/// The spellings accepted for negation, the canonical Unicode one first.
+pub const NOT_ALIASES: &[&str] = &["¬", "!", "~"];
+
+/// The set of operator symbols used when displaying an expression.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum Notation {
+    #[default]
+    Unicode,
+    Ascii,
+}
+
+impl Notation {
+    pub fn not_symbol(&self) -> &'static str {
+        match self {
+            Notation::Unicode => "¬",
+            Notation::Ascii => "!",
+        }
+    }
+
+    pub fn const_symbol(&self, value: bool) -> &'static str {
+        match (self, value) {
+            (Notation::Unicode, true) => "⊤",
+            (Notation::Unicode, false) => "⊥",
+            (Notation::Ascii, true) => "true",
+            (Notation::Ascii, false) => "false",
+        }
+    }
+}
+
+impl BinaryOpType {
+    pub const ALL: [BinaryOpType; 7] = [
+        BinaryOpType::And,
+        BinaryOpType::Or,
+        BinaryOpType::Implies,
+        BinaryOpType::Equivalent,
+        BinaryOpType::Xor,
+        BinaryOpType::Nand,
+        BinaryOpType::Nor,
+    ];
+
+    pub fn symbol(&self, notation: Notation) -> &'static str {
+        match notation {
+            Notation::Unicode => self.aliases()[0],
+            Notation::Ascii => self.aliases()[1],
+        }
+    }
+
+    /// The spellings accepted for the operator: the Unicode one, the canonical ASCII one and any
+    /// further ASCII variants.
+    pub fn aliases(&self) -> &'static [&'static str] {
+        match self {
+            BinaryOpType::And => &["∧", "&", "&&"],
+            BinaryOpType::Or => &["∨", "|", "||"],
+            BinaryOpType::Implies => &["→", "->", "=>"],
+            BinaryOpType::Equivalent => &["↔", "<->", "<=>"],
+            BinaryOpType::Xor => &["⊕", "^"],
+            BinaryOpType::Nand => &["↑", "!&"],
+            BinaryOpType::Nor => &["↓", "!|"],
+        }
+    }
+
+    /// Binding strength of the operator; higher binds tighter. Negation binds tighter than all of
+    /// these.
+    pub fn precedence(&self) -> u8 {
+        match self {
+            BinaryOpType::And | BinaryOpType::Nand => 5,
+            BinaryOpType::Xor => 4,
+            BinaryOpType::Or | BinaryOpType::Nor => 3,
+            BinaryOpType::Implies => 2,
+            BinaryOpType::Equivalent => 1,
+        }
+    }
+
+    pub fn is_right_associative(&self) -> bool {
+        matches!(self, BinaryOpType::Implies)
+    }
+}
+
 impl BinaryOp {
     pub fn new(ty: BinaryOpType, l: Expr, r: Expr) -> Self {
         Self { ty, l, r }
     }
 }
 
+/// The position of a node in the syntax tree of an expression.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TreePosition {
+    /// The number of nodes above the node; 0 for the root.
+    pub depth: usize,
+    /// The index of each node on the way from the root among the children of its parent.
+    pub path: Vec<usize>,
+    /// The index of the symbol of the node's parent in the list the position belongs to; for an
+    /// n-ary parent, its first symbol.
+    pub parent: Option<usize>,
+}
+
+/// Maps the given variables, in order, to the names `a`, `b`, `c`, ...
+pub(crate) fn canonical_names<'a>(
+    variables: impl IntoIterator<Item = &'a str>,
+) -> HashMap<String, String> {
+    let variables: Vec<&str> = variables.into_iter().collect();
+    variables
+        .iter()
+        .map(|v| v.to_string())
+        .zip(variable_names(variables.len()))
+        .collect()
+}
+
 impl fmt::Display for Expr {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         // write!(f, "({}, {})", self.x, self.y)
-        write!(f, "{}", self._to_string(false))
+        write!(f, "{}", self._to_string(false, Notation::Unicode))
     }
 }
 
 impl Expr {
-    fn _to_string(&self, is_deep: bool) -> String {
+    /// Combines the operands with ∧: no operands result in ⊤, a single one is returned as-is, two
+    /// are joined with a `BinaryOp` and more than that produce an `Expr::And`.
+    pub fn conjunction(operands: Vec<Expr>) -> Expr {
+        Self::nary(BinaryOpType::And, operands)
+    }
+
+    /// Combines the operands with ∨: no operands result in ⊥, a single one is returned as-is, two
+    /// are joined with a `BinaryOp` and more than that produce an `Expr::Or`.
+    pub fn disjunction(operands: Vec<Expr>) -> Expr {
+        Self::nary(BinaryOpType::Or, operands)
+    }
+
+    fn nary(ty: BinaryOpType, mut operands: Vec<Expr>) -> Expr {
+        match operands.len() {
+            0 => Expr::Const(ty == BinaryOpType::And),
+            1 => operands.pop().unwrap(),
+            2 => {
+                let r = operands.pop().unwrap();
+                let l = operands.pop().unwrap();
+                Expr::BinaryOp(Box::new(BinaryOp::new(ty, l, r)))
+            }
+            _ if ty == BinaryOpType::And => Expr::And(operands),
+            _ => Expr::Or(operands),
+        }
+    }
+
+    /// Merges nested conjunctions and disjunctions (both binary and n-ary) into n-ary nodes, e.g.
+    /// `(a ∧ b) ∧ (c ∧ d)` becomes `a ∧ b ∧ c ∧ d`.
+    pub fn flatten(&self) -> Expr {
+        if let Some(ty) = self.junction_type() {
+            let mut operands = Vec::new();
+            self.collect_operands(ty, &mut operands);
+
+            return Self::nary(ty, operands);
+        }
+
+        match self {
+            Expr::Var(_) | Expr::Const(_) => self.clone(),
+            Expr::Not(e) => Expr::Not(Box::new(e.flatten())),
+            Expr::BinaryOp(bop) => Expr::BinaryOp(Box::new(BinaryOp::new(
+                bop.ty,
+                bop.l.flatten(),
+                bop.r.flatten(),
+            ))),
+            Expr::And(_) | Expr::Or(_) => unreachable!(),
+        }
+    }
+
+    /// Returns `And` or `Or` if the expression is a (binary or n-ary) conjunction or disjunction.
+    pub(crate) fn junction_type(&self) -> Option<BinaryOpType> {
+        match self {
+            Expr::BinaryOp(bop) if matches!(bop.ty, BinaryOpType::And | BinaryOpType::Or) => {
+                Some(bop.ty)
+            }
+            Expr::And(_) => Some(BinaryOpType::And),
+            Expr::Or(_) => Some(BinaryOpType::Or),
+            _ => None,
+        }
+    }
+
+    fn collect_operands(&self, ty: BinaryOpType, operands: &mut Vec<Expr>) {
+        match self {
+            Expr::BinaryOp(bop) if bop.ty == ty => {
+                bop.l.collect_operands(ty, operands);
+                bop.r.collect_operands(ty, operands);
+            }
+            Expr::And(es) if ty == BinaryOpType::And => {
+                es.iter().for_each(|e| e.collect_operands(ty, operands))
+            }
+            Expr::Or(es) if ty == BinaryOpType::Or => {
+                es.iter().for_each(|e| e.collect_operands(ty, operands))
+            }
+            _ => operands.push(self.flatten()),
+        }
+    }
+
+    /// Displays the expression using the operator symbols of the given notation.
+    pub fn to_string_with(&self, notation: Notation) -> String {
+        self._to_string(false, notation)
+    }
+
+    fn _to_string(&self, is_deep: bool, notation: Notation) -> String {
         match self {
-            Expr::Var(s) => s.to_string(),
-            Expr::Not(e) => format!("¬{}", e._to_string(true)),
+            Expr::Var(v) => v.clone(),
+            Expr::Const(b) => notation.const_symbol(*b).to_string(),
+            Expr::Not(e) => format!("{}{}", notation.not_symbol(), e._to_string(true, notation)),
             Expr::BinaryOp(bop) => {
-                let (l, r) = (bop.l._to_string(true), bop.r._to_string(true));
-                let inner = match bop.ty {
-                    BinaryOpType::And => format!("{l} ∧ {r}"),
-                    BinaryOpType::Or => format!("{l} ∨ {r}"),
-                    BinaryOpType::Implies => format!("{l} → {r}"),
-                    BinaryOpType::Equivalent => {
-                        format!("{l} ↔ {r}")
-                    }
-                };
+                let (l, r) = (
+                    bop.l._to_string(true, notation),
+                    bop.r._to_string(true, notation),
+                );
+                let inner = format!("{l} {} {r}", bop.ty.symbol(notation));
+                if is_deep { format!("({inner})") } else { inner }
+            }
+            Expr::And(es) | Expr::Or(es) => {
+                let ty = self.junction_type().unwrap();
+                let inner = es
+                    .iter()
+                    .map(|e| e._to_string(true, notation))
+                    .collect::<Vec<_>>()
+                    .join(&format!(" {} ", ty.symbol(notation)));
                 if is_deep { format!("({inner})") } else { inner }
             }
         }
@@ -57,20 +256,142 @@ impl Expr {
 
     pub fn depth(&self) -> usize {
         match self {
-            Expr::Var(_) => 0,
+            Expr::Var(_) | Expr::Const(_) => 0,
             Expr::Not(e) => 1 + e.depth(),
             Expr::BinaryOp(bop) => 1 + bop.l.depth() + bop.r.depth(),
+            Expr::And(es) | Expr::Or(es) => 1 + es.iter().map(|e| e.depth()).sum::<usize>(),
+        }
+    }
+
+    /// The number of connectives on the longest path from the root to a leaf; this is what the
+    /// generator's max depth bounds.
+    pub fn height(&self) -> usize {
+        match self {
+            Expr::Var(_) | Expr::Const(_) => 0,
+            Expr::Not(e) => 1 + e.height(),
+            Expr::BinaryOp(bop) => 1 + bop.l.height().max(bop.r.height()),
+            Expr::And(es) | Expr::Or(es) => 1 + es.iter().map(|e| e.height()).max().unwrap(),
+        }
+    }
+
+    /// Returns the position of the node of every symbol of the expression (variables, constants
+    /// and operators), in the order in which they are displayed; every operator of an n-ary
+    /// conjunction or disjunction is listed, with the same position. Parentheses don't affect the
+    /// order, so it's also the order of the symbols in any input the expression was parsed from.
+    pub fn tree_positions(&self) -> Vec<TreePosition> {
+        // the symbols' nodes, numbered in pre-order, and their parents' numbers
+        let mut symbols = Vec::new();
+        let mut parents = Vec::new();
+        self.collect_tree_positions(None, Vec::new(), &mut symbols, &mut parents);
+
+        let mut first_symbols = HashMap::new();
+        for (i, (node, _)) in symbols.iter().enumerate() {
+            first_symbols.entry(*node).or_insert(i);
+        }
+
+        symbols
+            .into_iter()
+            .map(|(node, path)| TreePosition {
+                depth: path.len(),
+                path,
+                parent: parents[node].map(|parent| first_symbols[&parent]),
+            })
+            .collect()
+    }
+
+    fn collect_tree_positions(
+        &self,
+        parent: Option<usize>,
+        path: Vec<usize>,
+        symbols: &mut Vec<(usize, Vec<usize>)>,
+        parents: &mut Vec<Option<usize>>,
+    ) {
+        let node = parents.len();
+        parents.push(parent);
+        let child_path = |i: usize| [path.as_slice(), &[i]].concat();
+
+        match self {
+            Expr::Var(_) | Expr::Const(_) => symbols.push((node, path)),
+            Expr::Not(e) => {
+                symbols.push((node, path.clone()));
+                e.collect_tree_positions(Some(node), child_path(0), symbols, parents);
+            }
+            Expr::BinaryOp(bop) => {
+                bop.l
+                    .collect_tree_positions(Some(node), child_path(0), symbols, parents);
+                symbols.push((node, path.clone()));
+                bop.r
+                    .collect_tree_positions(Some(node), child_path(1), symbols, parents);
+            }
+            Expr::And(es) | Expr::Or(es) => {
+                for (i, e) in es.iter().enumerate() {
+                    if i != 0 {
+                        symbols.push((node, path.clone()));
+                    }
+                    e.collect_tree_positions(Some(node), child_path(i), symbols, parents);
+                }
+            }
         }
     }
 
     pub fn num_variables(&self) -> usize {
         match self {
             Expr::Var(_) => 1,
+            Expr::Const(_) => 0,
             Expr::Not(e) => e.num_variables(),
             Expr::BinaryOp(bop) => bop.l.num_variables() + bop.r.num_variables(),
+            Expr::And(es) | Expr::Or(es) => es.iter().map(|e| e.num_variables()).sum(),
         }
     }
 
+    /// Returns the distinct variables of the expression in the order of their first occurrence.
+    pub fn variables(&self) -> Vec<&str> {
+        let mut vars = Vec::new();
+        self.collect_variables(&mut vars);
+        vars
+    }
+
+    fn collect_variables<'a>(&'a self, vars: &mut Vec<&'a str>) {
+        match self {
+            Expr::Var(v) => {
+                if !vars.contains(&v.as_str()) {
+                    vars.push(v);
+                }
+            }
+            Expr::Const(_) => {}
+            Expr::Not(e) => e.collect_variables(vars),
+            Expr::BinaryOp(bop) => {
+                bop.l.collect_variables(vars);
+                bop.r.collect_variables(vars);
+            }
+            Expr::And(es) | Expr::Or(es) => es.iter().for_each(|e| e.collect_variables(vars)),
+        }
+    }
+
+    /// Returns the expression with every variable `v` renamed to `rename(v)`.
+    pub fn rename_variables(&self, rename: &impl Fn(&str) -> String) -> Expr {
+        match self {
+            Expr::Var(v) => Expr::Var(rename(v)),
+            Expr::Const(_) => self.clone(),
+            Expr::Not(e) => Expr::Not(Box::new(e.rename_variables(rename))),
+            Expr::BinaryOp(bop) => Expr::BinaryOp(Box::new(BinaryOp::new(
+                bop.ty,
+                bop.l.rename_variables(rename),
+                bop.r.rename_variables(rename),
+            ))),
+            Expr::And(es) => Expr::And(es.iter().map(|e| e.rename_variables(rename)).collect()),
+            Expr::Or(es) => Expr::Or(es.iter().map(|e| e.rename_variables(rename)).collect()),
+        }
+    }
+
+    /// Renames the variables to `a`, `b`, `c`, ... in the order of their first occurrence, so that
+    /// alpha-equivalent expressions (equal up to a consistent renaming of their variables) become
+    /// equal.
+    pub fn canonical_renaming(&self) -> Expr {
+        let renaming = canonical_names(self.variables());
+        self.rename_variables(&|v| renaming[v].clone())
+    }
+
     pub fn complexity(&self) -> usize {
         self.num_variables() + self.depth() * 2
     }
@@ -78,12 +399,15 @@ impl Expr {
     pub fn to_structure(&self) -> ExprStructure {
         match self {
             Expr::Var(_) => ExprStructure::Var,
+            Expr::Const(_) => ExprStructure::Const,
             Expr::Not(e) => ExprStructure::Not(Box::new(e.to_structure())),
             Expr::BinaryOp(bop) => ExprStructure::BinaryOp(Box::new(BinaryOpStructure {
                 ty: bop.ty,
                 l: bop.l.to_structure(),
                 r: bop.r.to_structure(),
             })),
+            Expr::And(es) => ExprStructure::And(es.iter().map(|e| e.to_structure()).collect()),
+            Expr::Or(es) => ExprStructure::Or(es.iter().map(|e| e.to_structure()).collect()),
         }
     }
 
@@ -95,9 +419,10 @@ impl Expr {
         *structures.get(&self.to_structure()).unwrap() as f32 / num_all_samples as f32
     }
 
-    pub fn evaluate(&self, state: &[(char, bool)]) -> bool {
+    pub fn evaluate(&self, state: &[(String, bool)]) -> bool {
         match self {
-            Expr::Var(c) => state.iter().find(|(c2, _)| c == c2).unwrap().1,
+            Expr::Var(v) => state.iter().find(|(v2, _)| v == v2).unwrap().1,
+            Expr::Const(b) => *b,
             Expr::Not(e) => !e.evaluate(state),
             Expr::BinaryOp(bop) => {
                 let l = bop.l.evaluate(state);
@@ -108,8 +433,13 @@ impl Expr {
                     BinaryOpType::Or => l || r,
                     BinaryOpType::Implies => !l || r,
                     BinaryOpType::Equivalent => l == r,
+                    BinaryOpType::Xor => l != r,
+                    BinaryOpType::Nand => !(l && r),
+                    BinaryOpType::Nor => !(l || r),
                 }
             }
+            Expr::And(es) => es.iter().all(|e| e.evaluate(state)),
+            Expr::Or(es) => es.iter().any(|e| e.evaluate(state)),
         }
     }
 }
@@ -117,8 +447,11 @@ impl Expr {
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum ExprStructure {
     Var,
+    Const,
     Not(Box<ExprStructure>),
     BinaryOp(Box<BinaryOpStructure>),
+    And(Vec<ExprStructure>),
+    Or(Vec<ExprStructure>),
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
@@ -127,3 +460,82 @@ pub struct BinaryOpStructure {
     pub l: ExprStructure,
     pub r: ExprStructure,
 }
+
+impl ExprStructure {
+    /// Displays the structure the way `Expr::_to_string` displays expressions, with `_` in place
+    /// of the variables and `#` in place of the constants.
+    fn _to_string(&self, is_deep: bool) -> String {
+        let notation = Notation::Unicode;
+        let inner = match self {
+            ExprStructure::Var => return "_".into(),
+            ExprStructure::Const => return "#".into(),
+            ExprStructure::Not(e) => {
+                return format!("{}{}", notation.not_symbol(), e._to_string(true));
+            }
+            ExprStructure::BinaryOp(bop) => format!(
+                "{} {} {}",
+                bop.l._to_string(true),
+                bop.ty.symbol(notation),
+                bop.r._to_string(true)
+            ),
+            ExprStructure::And(es) | ExprStructure::Or(es) => {
+                let ty = if matches!(self, ExprStructure::And(_)) {
+                    BinaryOpType::And
+                } else {
+                    BinaryOpType::Or
+                };
+                es.iter()
+                    .map(|e| e._to_string(true))
+                    .collect::<Vec<_>>()
+                    .join(&format!(" {} ", ty.symbol(notation)))
+            }
+        };
+
+        if is_deep { format!("({inner})") } else { inner }
+    }
+}
+
+/// Displays the structure like an expression, e.g. `¬_ → (_ ∧ #)`, with `_` standing for any
+/// variable and `#` for any constant.
+impl fmt::Display for ExprStructure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self._to_string(false))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::Parser;
+
+    #[test]
+    fn tree_positions() {
+        let expr = Parser::new("¬a ∧ (b ∨ c ∨ ⊤)").parse().unwrap();
+        let positions = expr.tree_positions();
+
+        let position = |depth, path: &[usize], parent| TreePosition {
+            depth,
+            path: path.to_vec(),
+            parent,
+        };
+        assert_eq!(
+            positions,
+            [
+                position(1, &[0], Some(2)),
+                position(2, &[0, 0], Some(0)),
+                position(0, &[], None),
+                position(2, &[1, 0], Some(4)),
+                position(1, &[1], Some(2)),
+                position(2, &[1, 1], Some(4)),
+                position(1, &[1], Some(2)),
+                position(2, &[1, 2], Some(4)),
+            ]
+        );
+    }
+
+    #[test]
+    fn structure() {
+        let expr = Parser::new("¬a → (b ∧ ⊤ ∧ ¬(c ∨ ⊥))").parse().unwrap();
+        assert_eq!(expr.to_structure().to_string(), "¬_ → (_ ∧ # ∧ ¬(_ ∨ #))");
+    }
+}
@@ -1,13 +1,26 @@
-use std::collections::HashSet;
-
+use mlml_util::{GenerationStrategy, OperatorWeights};
 use rand::Rng;
-use rand::seq::{IndexedRandom, IteratorRandom};
+use rand::seq::{IndexedRandom, IteratorRandom, SliceRandom};
+
+use crate::{
+    expr::{BinaryOp, BinaryOpType, Expr},
+    sample::Assignment,
+};
 
-use crate::expr::{BinaryOp, BinaryOpType, Expr};
+/// The number of expressions the distinct-variables strategy may discard for having too few variable
+/// occurrences before the count is deemed unreachable.
+const MAX_DISTINCT_VARS_ATTEMPTS: usize = 1_000_000;
 
 pub struct ExprGenerator {
     max_depth: usize,
     max_vars: usize,
+    const_probability: f64,
+    max_arity: usize,
+    operator_weights: OperatorWeights,
+    strategy: GenerationStrategy,
+    /// The number of expressions with each number of connectives, weighted by the operator
+    /// weights; only used by the exact-size strategy.
+    expression_counts: Vec<u128>,
 }
 
 impl ExprGenerator {
@@ -15,105 +28,393 @@ impl ExprGenerator {
         ExprGenerator {
             max_depth,
             max_vars,
+            const_probability: 0.0,
+            max_arity: 2,
+            operator_weights: OperatorWeights::default(),
+            strategy: GenerationStrategy::default(),
+            expression_counts: Vec::new(),
         }
     }
 
-    pub fn generate<R: Rng>(&self, range: &[char], rng: &mut R) -> Expr {
-        let mut vars = HashSet::new();
-        self.generate_with_depth(0, range, &mut vars, rng)
+    /// Sets the probability with which a leaf becomes a constant (⊤ or ⊥) instead of a variable.
+    pub fn with_const_probability(mut self, const_probability: f64) -> Self {
+        self.const_probability = const_probability;
+        self
+    }
+
+    /// Sets the maximum number of operands of a generated conjunction or disjunction; the arity of
+    /// each one is chosen uniformly between 2 and this value.
+    pub fn with_max_arity(mut self, max_arity: usize) -> Self {
+        debug_assert!(max_arity >= 2);
+        self.max_arity = max_arity;
+        self
+    }
+
+    /// Sets the relative weights with which each kind of node is picked above the max depth; they
+    /// must pass [`OperatorWeights::validate`].
+    pub fn with_operator_weights(mut self, operator_weights: OperatorWeights) -> Self {
+        debug_assert!(operator_weights.validate().is_ok());
+        self.operator_weights = operator_weights;
+        self.update_expression_counts();
+        self
+    }
+
+    /// Sets the way in which the shapes of the expressions are picked; the settings must pass
+    /// `DatasetConfig::validate` along with the strategy.
+    pub fn with_strategy(mut self, strategy: GenerationStrategy) -> Self {
+        self.strategy = strategy;
+        self.update_expression_counts();
+        self
+    }
+
+    /// Counts the expressions the exact-size strategy picks from, which depend on the operator
+    /// weights.
+    fn update_expression_counts(&mut self) {
+        self.expression_counts = match self.strategy {
+            GenerationStrategy::ExactSize { size } => self
+                .operator_weights
+                .expression_counts(size)
+                .expect("the number of expressions of the exact size overflows"),
+            _ => Vec::new(),
+        };
+    }
+
+    pub fn operator_weights(&self) -> &OperatorWeights {
+        &self.operator_weights
     }
 
+    pub fn generate<R: Rng>(&self, range: &[String], rng: &mut R) -> Expr {
+        let mut vars = Vec::new();
+        match self.strategy {
+            GenerationStrategy::Recursive => {
+                self.generate_with_depth(0, false, range, &mut vars, rng)
+            }
+            GenerationStrategy::ExactDepth => {
+                self.generate_with_depth(0, true, range, &mut vars, rng)
+            }
+            GenerationStrategy::ExactSize { size } => {
+                self.generate_with_size(size, range, &mut vars, rng)
+            }
+            GenerationStrategy::DistinctVariables { count } => {
+                self.generate_with_distinct_vars(count, range, rng)
+            }
+        }
+    }
+
+    /// Picks each node according to the operator weights, down to the max depth; if `exact` is
+    /// set, the expression reaches the max depth.
     fn generate_with_depth<R: Rng>(
         &self,
         depth: usize,
-        range: &[char],
-        vars: &mut HashSet<char>,
+        exact: bool,
+        range: &[String],
+        vars: &mut Vec<String>,
         rng: &mut R,
     ) -> Expr {
         if depth >= self.max_depth {
-            let var = if vars.len() < self.max_vars {
-                let v = self.random_variable(range, rng);
-                vars.insert(v);
-                v
-            } else {
-                *vars.iter().choose(rng).unwrap()
-            };
-
-            return Expr::Var(var);
+            return self.generate_leaf(range, vars, rng);
         }
 
-        let choices = [
-            ("var", 5),
-            ("not", 1),
-            ("and", 1),
-            ("or", 1),
-            ("impl", 3),
-            ("equiv", 1),
-        ];
+        let choices = self.operator_weights.entries();
         let choice = choices
+            .iter()
+            .filter(|(c, _)| !exact || *c != "var")
+            .collect::<Vec<_>>()
             .choose_weighted(rng, |(_, w)| *w)
-            .map(|(c, _)| c)
+            .map(|(c, _)| *c)
             .unwrap();
 
-        match &**choice {
-            "var" => {
-                let var = if vars.len() < self.max_vars {
-                    let v = self.random_variable(range, rng);
-                    vars.insert(v);
-                    v
-                } else {
-                    *vars.iter().choose(rng).unwrap()
-                };
-                Expr::Var(var)
+        let arity = match choice {
+            "var" => return self.generate_leaf(range, vars, rng),
+            "not" => 1,
+            "and" | "or" if self.max_arity > 2 => rng.random_range(2..=self.max_arity),
+            _ => 2,
+        };
+        // with an exact depth, one of the operands has to reach it
+        let exact_operand = exact.then(|| rng.random_range(0..arity));
+        let mut operands: Vec<Expr> = (0..arity)
+            .map(|i| {
+                self.generate_with_depth(depth + 1, exact_operand == Some(i), range, vars, rng)
+            })
+            .collect();
+
+        match choice {
+            "not" => Expr::Not(Box::new(operands.pop().unwrap())),
+            "and" => Expr::conjunction(operands),
+            "or" => Expr::disjunction(operands),
+            bop => {
+                let r = operands.pop().unwrap();
+                let l = operands.pop().unwrap();
+                Expr::BinaryOp(Box::new(BinaryOp::new(binary_op_type(bop), l, r)))
             }
-            "not" => Expr::Not(Box::new(self.generate_with_depth(
-                depth + 1,
+        }
+    }
+
+    /// Picks an expression with the given number of connectives uniformly among all the
+    /// expressions made of negations and binary connectives, each connective counting as many
+    /// times as its weight, using the recursive method: the root is a negation or a binary
+    /// connective with a given split of the remaining connectives with a probability proportional
+    /// to the number of expressions it allows. The binary connectives are then picked according
+    /// to the operator weights.
+    fn generate_with_size<R: Rng>(
+        &self,
+        size: usize,
+        range: &[String],
+        vars: &mut Vec<String>,
+        rng: &mut R,
+    ) -> Expr {
+        if size == 0 {
+            return self.generate_leaf(range, vars, rng);
+        }
+
+        let counts = &self.expression_counts;
+        let not_weight = self.operator_weights.not as u128;
+        let binary_weight = self.operator_weights.binary_total() as u128;
+        let mut r = rng.random_range(0..counts[size]);
+        if r < not_weight * counts[size - 1] {
+            return Expr::Not(Box::new(self.generate_with_size(
+                size - 1,
                 range,
                 vars,
                 rng,
-            ))),
-            bop => {
-                let op = match bop {
-                    "and" => BinaryOpType::And,
-                    "or" => BinaryOpType::Or,
-                    "impl" => BinaryOpType::Implies,
-                    "equiv" => BinaryOpType::Equivalent,
-                    _ => unreachable!(),
-                };
-
-                Expr::BinaryOp(Box::new(BinaryOp::new(
-                    op,
-                    self.generate_with_depth(depth + 1, range, vars, rng),
-                    self.generate_with_depth(depth + 1, range, vars, rng),
-                )))
+            )));
+        }
+        r -= not_weight * counts[size - 1];
+
+        for l_size in 0..size {
+            let r_size = size - 1 - l_size;
+            let num_exprs = binary_weight * counts[l_size] * counts[r_size];
+            if r < num_exprs {
+                let l = self.generate_with_size(l_size, range, vars, rng);
+                let r = self.generate_with_size(r_size, range, vars, rng);
+                return Expr::BinaryOp(Box::new(BinaryOp::new(self.random_binary_op(rng), l, r)));
             }
+            r -= num_exprs;
         }
+
+        unreachable!()
     }
 
-    fn random_variable<R: Rng>(&self, range: &[char], rng: &mut R) -> char {
-        range.iter().copied().choose(rng).unwrap()
+    /// Generates an expression like the recursive strategy, with exactly `count` distinct
+    /// variables.
+    fn generate_with_distinct_vars<R: Rng>(
+        &self,
+        count: usize,
+        range: &[String],
+        rng: &mut R,
+    ) -> Expr {
+        for _ in 0..MAX_DISTINCT_VARS_ATTEMPTS {
+            let expr = self.generate_with_depth(0, false, range, &mut Vec::new(), rng);
+            let num_leaves = expr.num_variables();
+            if num_leaves < count {
+                continue;
+            }
+
+            // every chosen variable occurs at least once, in random positions
+            let chosen: Vec<&String> = range.choose_multiple(rng, count).collect();
+            let mut names: Vec<String> = chosen.iter().map(|v| v.to_string()).collect();
+            names.extend((count..num_leaves).map(|_| chosen.choose(rng).unwrap().to_string()));
+            names.shuffle(rng);
+
+            return assign_variables(&expr, &mut names.into_iter());
+        }
+
+        panic!(
+            "no expression with {count} variable occurrences was generated in \
+             {MAX_DISTINCT_VARS_ATTEMPTS} attempts; the configuration may not allow for it"
+        );
     }
-}
 
-pub fn generate_state<R: Rng>(expr: &Expr, rng: &mut R) -> Box<[(char, bool)]> {
-    let mut state = Vec::new();
-    generate_state_recurse(expr, &mut state, rng);
+    fn random_binary_op<R: Rng>(&self, rng: &mut R) -> BinaryOpType {
+        let choices = self.operator_weights.entries();
+        let choice = choices
+            .iter()
+            .filter(|(c, _)| !["var", "not"].contains(c))
+            .collect::<Vec<_>>()
+            .choose_weighted(rng, |(_, w)| *w)
+            .map(|(c, _)| *c)
+            .unwrap();
+
+        binary_op_type(choice)
+    }
+
+    fn generate_leaf<R: Rng>(&self, range: &[String], vars: &mut Vec<String>, rng: &mut R) -> Expr {
+        if self.const_probability > 0.0 && rng.random_bool(self.const_probability) {
+            return Expr::Const(rng.random_bool(0.5));
+        }
 
-    state.into_boxed_slice()
+        let var = if vars.len() < self.max_vars {
+            let v = self.random_variable(range, rng);
+            if !vars.contains(&v) {
+                vars.push(v.clone());
+            }
+            v
+        } else {
+            vars.choose(rng).unwrap().clone()
+        };
+
+        Expr::Var(var)
+    }
+
+    fn random_variable<R: Rng>(&self, range: &[String], rng: &mut R) -> String {
+        range.iter().choose(rng).unwrap().clone()
+    }
 }
 
-fn generate_state_recurse<R: Rng>(expr: &Expr, state: &mut Vec<(char, bool)>, rng: &mut R) {
+/// Maps the name of a binary operator in the `OperatorWeights` to its type.
+fn binary_op_type(name: &str) -> BinaryOpType {
+    match name {
+        "and" => BinaryOpType::And,
+        "or" => BinaryOpType::Or,
+        "impl" => BinaryOpType::Implies,
+        "equiv" => BinaryOpType::Equivalent,
+        "xor" => BinaryOpType::Xor,
+        "nand" => BinaryOpType::Nand,
+        "nor" => BinaryOpType::Nor,
+        _ => unreachable!(),
+    }
+}
+
+/// Replaces the variables of the expression, in order, with the given names.
+fn assign_variables(expr: &Expr, names: &mut impl Iterator<Item = String>) -> Expr {
     match expr {
-        Expr::Var(v) => {
-            if !state.iter().map(|(c, _)| c).any(|c| c == v) {
-                state.push((*v, rng.random_bool(0.5)));
-            }
-        }
-        Expr::Not(e) => generate_state_recurse(e, state, rng),
+        Expr::Var(_) => Expr::Var(names.next().unwrap()),
+        Expr::Const(_) => expr.clone(),
+        Expr::Not(e) => Expr::Not(Box::new(assign_variables(e, names))),
         Expr::BinaryOp(bop) => {
-            let BinaryOp { ty: _, l, r } = &**bop;
-            generate_state_recurse(l, state, rng);
-            generate_state_recurse(r, state, rng);
+            let l = assign_variables(&bop.l, names);
+            let r = assign_variables(&bop.r, names);
+            Expr::BinaryOp(Box::new(BinaryOp::new(bop.ty, l, r)))
+        }
+        Expr::And(es) => Expr::And(es.iter().map(|e| assign_variables(e, names)).collect()),
+        Expr::Or(es) => Expr::Or(es.iter().map(|e| assign_variables(e, names)).collect()),
+    }
+}
+
+/// Returns `count` distinct variable names: the letters `a` to `z`, followed by the same letters
+/// with increasing numeric suffixes (`a1`, ..., `z1`, `a2`, ...).
+pub fn variable_names(count: usize) -> Vec<String> {
+    ('a'..='z')
+        .map(|c| c.to_string())
+        .chain((1..).flat_map(|n| ('a'..='z').map(move |c| format!("{c}{n}"))))
+        .take(count)
+        .collect()
+}
+
+pub fn generate_state<R: Rng>(expr: &Expr, rng: &mut R) -> Assignment {
+    let bindings = expr
+        .variables()
+        .into_iter()
+        .map(|v| (v.to_owned(), rng.random_bool(0.5)))
+        .collect::<Vec<_>>();
+
+    Assignment::new(bindings).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use rand::SeedableRng;
+    use rand_xorshift::XorShiftRng;
+
+    use super::*;
+
+    #[test]
+    fn deterministic() {
+        let generator = ExprGenerator::new(4, 3).with_max_arity(3);
+        let range = variable_names(10);
+        let generate = |seed| {
+            let mut rng = XorShiftRng::seed_from_u64(seed);
+            (0..100)
+                .map(|_| {
+                    let expr = generator.generate(&range, &mut rng);
+                    (generate_state(&expr, &mut rng), expr)
+                })
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(generate(1), generate(1));
+        assert_ne!(generate(1), generate(2));
+    }
+
+    #[test]
+    fn exact_size() {
+        // with the weight of negations equal to the sum of the binary ones, the shapes are uniform
+        let weights = OperatorWeights {
+            not: 2,
+            and: 1,
+            or: 1,
+            implies: 0,
+            equivalent: 0,
+            xor: 0,
+            nand: 0,
+            nor: 0,
+            ..Default::default()
+        };
+        assert_eq!(weights.expression_counts(3), Some(vec![1, 4, 24, 176]));
+
+        let mut rng = XorShiftRng::seed_from_u64(0);
+        // the strategy can be set before the weights it depends on
+        let generator = ExprGenerator::new(0, 3)
+            .with_strategy(GenerationStrategy::ExactSize { size: 2 })
+            .with_operator_weights(weights);
+        let range = variable_names(3);
+
+        // each of the 6 shapes of size 2 should come up about equally often
+        let mut shape_counts = HashMap::new();
+        for _ in 0..6000 {
+            let expr = generator.generate(&range, &mut rng);
+            assert_eq!(expr.depth(), 2);
+            let shape = expr.rename_variables(&|_| "_".into()).to_string();
+            let shape = shape.replace(['∧', '∨', '→', '↔', '⊕', '↑', '↓'], "*");
+            *shape_counts.entry(shape).or_insert(0) += 1;
+        }
+        assert_eq!(shape_counts.len(), 6);
+        assert!(shape_counts.values().all(|n| (850..1150).contains(n)));
+
+        // without negations, only the 2 binary shapes remain
+        let weights = OperatorWeights { not: 0, ..weights };
+        let generator = ExprGenerator::new(0, 3)
+            .with_operator_weights(weights)
+            .with_strategy(GenerationStrategy::ExactSize { size: 5 });
+        for _ in 0..100 {
+            let expr = generator.generate(&range, &mut rng);
+            assert_eq!(expr.tree_positions().len() - expr.num_variables(), 5);
+            assert!(!expr.to_string().contains('¬'));
+        }
+
+        // the largest size whose count fits is supported
+        let size = (1..)
+            .find(|size| {
+                OperatorWeights::default()
+                    .expression_counts(*size)
+                    .is_none()
+            })
+            .unwrap()
+            - 1;
+        let generator =
+            ExprGenerator::new(0, 3).with_strategy(GenerationStrategy::ExactSize { size });
+        let expr = generator.generate(&range, &mut rng);
+        assert_eq!(expr.tree_positions().len() - expr.num_variables(), size);
+    }
+
+    #[test]
+    fn exact_depth_and_distinct_variables() {
+        let mut rng = XorShiftRng::seed_from_u64(0);
+        let range = variable_names(5);
+
+        let generator = ExprGenerator::new(3, 5)
+            .with_max_arity(3)
+            .with_strategy(GenerationStrategy::ExactDepth);
+        for _ in 0..100 {
+            assert_eq!(generator.generate(&range, &mut rng).height(), 3);
+        }
+
+        let generator = ExprGenerator::new(3, 5)
+            .with_strategy(GenerationStrategy::DistinctVariables { count: 4 });
+        for _ in 0..100 {
+            assert_eq!(generator.generate(&range, &mut rng).variables().len(), 4);
         }
     }
 }
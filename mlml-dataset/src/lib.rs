@@ -1,3 +1,8 @@
+pub mod analysis;
 pub mod expr;
 pub mod generator;
+pub mod normal_form;
 pub mod parser;
+pub mod sample;
+pub mod simplify;
+pub mod split;
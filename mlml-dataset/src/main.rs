@@ -1,39 +1,163 @@
-mod expr;
-mod generator;
-mod parser;
+use std::{collections::BTreeMap, env, fmt, fs};
 
-use std::{
-    collections::{HashMap, HashSet},
-    fs,
+use mlml_dataset::{
+    expr::{Expr, Notation},
+    generator::*,
+    parser::Parser,
+    sample::{Assignment, Sample, token_count},
+    simplify::{Rule, Simplifier},
+    split::{EquivalenceFilter, SplitFilter, TEST, TRAIN, VALID},
 };
-
-use mlml_util::{MlmlConfig, config_path};
-use rand::{SeedableRng, seq::IteratorRandom};
+use mlml_util::{DatasetMetadata, MlmlConfig, Task, config_path};
+use rand::{Rng, SeedableRng, seq::IteratorRandom};
 use rand_xorshift::XorShiftRng;
 
-use crate::{expr::Expr, generator::*, parser::*};
+/// The number of consecutive candidates a split may reject before the class it waits for is deemed
+/// unreachable with the configuration.
+const MAX_ATTEMPTS: usize = 1_000_000;
 
-#[derive(Clone, PartialEq, Eq, Hash)]
 struct Entry {
     expr: Expr,
-    state: Box<[(char, bool)]>,
-    ret: bool,
+    // only present in the evaluation task
+    state: Option<Assignment>,
+    label: &'static str,
+}
+
+impl Entry {
+    /// The entry with its variables renamed canonically; alpha-equivalent entries share it.
+    fn canonical_renaming(&self) -> Entry {
+        let (expr, state) = match &self.state {
+            Some(state) => {
+                let sample = Sample::new(state.clone(), self.expr.clone())
+                    .unwrap()
+                    .canonical_renaming();
+                (sample.expr, Some(sample.state))
+            }
+            None => (self.expr.canonical_renaming(), None),
+        };
+
+        Entry {
+            expr,
+            state,
+            label: self.label,
+        }
+    }
+}
+
+impl fmt::Display for Entry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.state {
+            Some(state) => write!(f, "{state} {}", self.expr),
+            None => write!(f, "{}", self.expr),
+        }
+    }
+}
+
+/// Returns the seed passed as `--seed <seed>`, which takes precedence over the configured one.
+fn seed_arg() -> Option<u64> {
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == "--seed" {
+            let seed = args.next().expect("missing value for --seed");
+            return Some(seed.parse().expect("the seed must be an unsigned integer"));
+        }
+    }
+
+    None
 }
 
 fn main() {
     let config_str = fs::read_to_string(config_path()).unwrap();
     let config: MlmlConfig = serde_json::from_str(&config_str).unwrap();
+    if let Err(e) = config.dataset.validate() {
+        panic!("invalid dataset config: {e}");
+    }
+
+    let seed = seed_arg()
+        .or(config.dataset.seed)
+        .unwrap_or_else(|| rand::rng().random());
+    println!("Generating the dataset with seed {seed}");
 
-    let generator = ExprGenerator::new(config.dataset.max_depth, config.dataset.max_variables);
+    let mut split_filter = SplitFilter::new(config.dataset.split);
+    let generators = [TRAIN, VALID, TEST].map(|split| {
+        let max_depth = split_filter
+            .max_depth(split)
+            .unwrap_or(config.dataset.max_depth);
+        ExprGenerator::new(max_depth, config.dataset.max_variables)
+            .with_const_probability(config.dataset.const_probability)
+            .with_max_arity(config.dataset.max_arity)
+            .with_operator_weights(config.dataset.operator_weights)
+            .with_strategy(config.dataset.generation)
+    });
+    let notation = if config.dataset.ascii_notation {
+        Notation::Ascii
+    } else {
+        Notation::Unicode
+    };
+
+    let reducer = Simplifier::new(&Rule::REDUCTIONS);
+
+    let variable_pool = variable_names(config.dataset.variable_pool_size);
 
     let _ = std::fs::remove_file(&config.dataset.db_path);
 
-    let mut rng = XorShiftRng::from_rng(&mut rand::rng());
-    let mut seen_all_entries = HashSet::new();
-    let mut seen_all_structures = HashMap::new();
+    let mut connection = rusqlite::Connection::open(&config.dataset.db_path).unwrap();
+    // the rows are written as they are generated, in a single transaction
+    let transaction = connection.transaction().unwrap();
+    let split_names = ["train", "valid", "test"];
+    for ty in split_names {
+        let table_creation_query = format!(
+            "
+            CREATE TABLE {ty} (
+                expression TEXT,
+                result TEXT,
+                complexity INTEGER,
+                rarity REAL,
+                row_id INTEGER PRIMARY KEY,
+                structure TEXT
+            )
+        "
+        );
+
+        transaction.execute(&table_creation_query, ()).unwrap();
+    }
+    let mut insert_statements = split_names.map(|ty| {
+        transaction
+            .prepare(&format!(
+                "INSERT INTO {ty} (expression, result, complexity, structure) \
+                 VALUES (?1, ?2, ?3, ?4)"
+            ))
+            .unwrap()
+    });
+
+    // the samples seen so far are kept in the database rather than in memory; the label of a
+    // sample is determined by its text, so it identifies the entry
+    transaction
+        .execute_batch(
+            "CREATE TEMP TABLE seen_samples (sample TEXT PRIMARY KEY);
+             CREATE TEMP TABLE seen_canonical_samples (sample TEXT PRIMARY KEY);",
+        )
+        .unwrap();
+    let mut insert_seen_sample = transaction
+        .prepare("INSERT OR IGNORE INTO seen_samples (sample) VALUES (?1)")
+        .unwrap();
+    let mut insert_seen_canonical_sample = transaction
+        .prepare("INSERT OR IGNORE INTO seen_canonical_samples (sample) VALUES (?1)")
+        .unwrap();
+    let mut equivalence_filter = EquivalenceFilter::new(&transaction).unwrap();
 
-    let mut samples = [HashSet::new(), HashSet::new(), HashSet::new()];
-    let mut next_wanted_results = [true, true, true];
+    let mut rng = XorShiftRng::seed_from_u64(seed);
+    let mut alpha_collisions = 0;
+    let mut structure_histogram: BTreeMap<String, usize> = BTreeMap::new();
+    let mut max_sample_length = 0;
+
+    let mut split_counts = [0, 0, 0];
+    // the classes are balanced by cycling through them in each split
+    let classes = config.dataset.task.classes();
+    let mut next_wanted_labels = [0, 0, 0];
+    // the candidates each split rejected since it accepted its last sample
+    let mut attempts = [0, 0, 0];
+    let class = |name: &str| *classes.iter().find(|c| **c == name).unwrap();
     let target_split_counts = &[
         config.dataset.train_samples_count,
         config.dataset.valid_samples_count,
@@ -42,115 +166,187 @@ fn main() {
     let mut done = vec![];
 
     while done.len() != 3 {
-        for (i, split_samples) in samples.iter_mut().enumerate() {
+        for i in 0..3 {
             if done.contains(&i) {
                 continue;
             }
+            attempts[i] += 1;
+            assert!(
+                attempts[i] <= MAX_ATTEMPTS,
+                "no {} sample with the label '{}' was found in {MAX_ATTEMPTS} attempts; the \
+                 configuration may not allow for it",
+                split_names[i],
+                classes[next_wanted_labels[i]]
+            );
 
-            let range = ('a'..='z').choose_multiple(&mut rng, config.dataset.max_variables);
+            let range = variable_pool
+                .iter()
+                .cloned()
+                .choose_multiple(&mut rng, config.dataset.max_variables);
 
-            let expr = generator.generate(&range, &mut rng);
-            let state = generate_state(&expr, &mut rng);
-            let ret = expr.evaluate(&state);
-            let entry = Entry { expr, state, ret };
+            let expr = generators[i].generate(&range, &mut rng);
+            if !split_filter.accepts(i, &expr) {
+                continue;
+            }
+            if config.dataset.reject_reducible && !reducer.simplify(&expr).rules.is_empty() {
+                continue;
+            }
 
-            if ret == next_wanted_results[i] && seen_all_entries.insert(entry.clone()) {
-                next_wanted_results[i] = !ret;
-            } else {
+            let (state, label) = match config.dataset.task {
+                Task::Evaluation => {
+                    let state = generate_state(&expr, &mut rng);
+                    let ret = expr.evaluate(state.bindings());
+                    (Some(state), class(&ret.to_string()))
+                }
+                Task::Validity => (None, class(&expr.validity().to_string())),
+                Task::Satisfiability => {
+                    let validity = expr.validity();
+                    let label = if validity.is_satisfiable() {
+                        "satisfiable"
+                    } else {
+                        "unsatisfiable"
+                    };
+                    (None, class(label))
+                }
+            };
+            if label != classes[next_wanted_labels[i]] {
                 continue;
             }
 
-            *seen_all_structures
-                .entry(entry.expr.to_structure())
-                .or_default() += 1;
-            split_samples.insert(entry);
+            let canonical_table = config
+                .dataset
+                .reject_equivalent_across_splits
+                .then(|| EquivalenceFilter::canonical_table(&expr))
+                .flatten();
+            if let Some(canonical_table) = &canonical_table
+                && !equivalence_filter.accepts(i, canonical_table).unwrap()
+            {
+                continue;
+            }
 
-            if split_samples.len() == target_split_counts[i] {
-                done.push(i);
+            let entry = Entry { expr, state, label };
+            // a row is only inserted if the sample wasn't seen yet
+            if insert_seen_sample.execute((entry.to_string(),)).unwrap() == 0 {
+                continue;
             }
-        }
-    }
+            if config.dataset.dedup_alpha_equivalent
+                && insert_seen_canonical_sample
+                    .execute((entry.canonical_renaming().to_string(),))
+                    .unwrap()
+                    == 0
+            {
+                alpha_collisions += 1;
+                continue;
+            }
+            next_wanted_labels[i] = (next_wanted_labels[i] + 1) % classes.len();
+            attempts[i] = 0;
+            if let Some(canonical_table) = canonical_table {
+                equivalence_filter.insert(i, &canonical_table).unwrap();
+            }
+            split_filter.insert(i, &entry.expr);
 
-    let connection = rusqlite::Connection::open(&config.dataset.db_path).unwrap();
+            let structure = entry.expr.to_structure().to_string();
+            *structure_histogram.entry(structure.clone()).or_default() += 1;
+            max_sample_length =
+                max_sample_length.max(token_count(entry.state.as_ref(), &entry.expr));
 
-    for (split_samples, ty) in [
-        (&samples[0], "train"),
-        (&samples[1], "valid"),
-        (&samples[2], "test"),
-    ] {
-        let table_creation_query = format!(
-            "
-            CREATE TABLE {ty} (
-                expression TEXT,
-                result TEXT,
-                complexity INTEGER,
-                rarity REAL,
-                row_id INTEGER PRIMARY KEY
-            )
-        "
-        );
+            let expr_str = entry.expr.to_string_with(notation);
+            let sample_str = if let Some(state) = &entry.state {
+                let sample_str = format!("{state} {expr_str}");
+                let sample = sample_str.parse::<Sample>().unwrap();
+                assert_eq!(sample.evaluate().to_string(), entry.label);
+                sample_str
+            } else {
+                assert_eq!(Parser::new(&expr_str).parse().as_ref(), Ok(&entry.expr));
+                expr_str
+            };
+            insert_statements[i]
+                .execute((sample_str, entry.label, entry.expr.complexity(), structure))
+                .unwrap();
 
-        connection.execute(&table_creation_query, ()).unwrap();
-
-        let mut data_query =
-            format!("INSERT INTO {ty} (expression, result, complexity, rarity) VALUES ");
-
-        let mut iter = split_samples.iter().peekable();
-        let mut row = String::new();
-        while let Some(entry) = iter.next() {
-            let expr_str = entry.expr.to_string();
-            assert!(Parser::new(&expr_str).parse().is_ok());
-
-            row.push_str(&format!(
-                "('{} {}', '{}', {}, {})",
-                stringify_state(&entry.state),
-                expr_str,
-                entry.ret,
-                entry.expr.complexity(),
-                entry
-                    .expr
-                    .rarity(seen_all_entries.len(), &seen_all_structures),
-            ));
-            if iter.peek().is_some() {
-                row.push_str(", ");
+            split_counts[i] += 1;
+            if split_counts[i] == target_split_counts[i] {
+                done.push(i);
             }
-            data_query.push_str(&row);
-            row.clear();
         }
+    }
+    drop(insert_statements);
+    drop(insert_seen_sample);
+    drop(insert_seen_canonical_sample);
+    drop(equivalence_filter);
+    transaction
+        .execute_batch(
+            "DROP TABLE seen_samples;
+             DROP TABLE seen_canonical_samples;
+             DROP TABLE canonical_table_splits;",
+        )
+        .unwrap();
 
-        connection.execute(&data_query, ()).unwrap();
+    if config.dataset.dedup_alpha_equivalent {
+        println!("Removed {alpha_collisions} alpha-equivalent duplicates");
     }
-}
 
-fn stringify_state(state: &[(char, bool)]) -> String {
-    let (mut ts, mut fs) = (Vec::new(), Vec::new());
-    for (c, b) in state {
-        if *b {
-            ts.push(c);
-        } else {
-            fs.push(c);
+    // the rarity of a sample is the share of all the samples with its structure, so it can only be
+    // filled in once all of them are known
+    transaction
+        .execute(
+            "CREATE TEMP TABLE structure_counts (structure TEXT PRIMARY KEY, count INTEGER)",
+            (),
+        )
+        .unwrap();
+    {
+        let mut insert_count = transaction
+            .prepare("INSERT INTO structure_counts (structure, count) VALUES (?1, ?2)")
+            .unwrap();
+        for (structure, count) in &structure_histogram {
+            insert_count.execute((structure, count)).unwrap();
         }
     }
+    let total_count: usize = split_counts.iter().sum();
+    for ty in split_names {
+        transaction
+            .execute(
+                &format!(
+                    "UPDATE {ty} SET rarity = CAST((SELECT count FROM structure_counts \
+                     WHERE structure_counts.structure = {ty}.structure) AS REAL) / ?1"
+                ),
+                (total_count,),
+            )
+            .unwrap();
+    }
+    transaction
+        .execute("DROP TABLE structure_counts", ())
+        .unwrap();
 
-    let mut ret = String::from("[");
+    let metadata = DatasetMetadata {
+        version: env!("CARGO_PKG_VERSION").into(),
+        config: config.dataset.clone(),
+        seed,
+        operator_weights: config.dataset.operator_weights,
+        counts: split_names
+            .into_iter()
+            .zip(split_counts)
+            .map(|(ty, count)| (ty.to_owned(), count))
+            .collect(),
+        structure_histogram,
+        max_sample_length,
+        alpha_collisions,
+    };
 
-    for (vars, val) in [(&ts, "true"), (&fs, "false")] {
-        if !vars.is_empty() {
-            let mut iter = vars.iter().peekable();
-            while let Some(c) = iter.next() {
-                ret.push(**c);
-                if iter.peek().is_some() {
-                    ret.push_str(", ");
-                }
-            }
-            ret.push_str(&format!(": {val}"));
-            if !ts.is_empty() && !fs.is_empty() && val == "true" {
-                ret.push_str("; ");
-            }
+    transaction
+        .execute(
+            "CREATE TABLE metadata (key TEXT PRIMARY KEY, value TEXT)",
+            (),
+        )
+        .unwrap();
+    {
+        let mut insert_metadata = transaction
+            .prepare("INSERT INTO metadata (key, value) VALUES (?1, ?2)")
+            .unwrap();
+        for (key, value) in metadata.to_rows() {
+            insert_metadata.execute((key, value)).unwrap();
         }
     }
 
-    ret.push(']');
-
-    ret
+    transaction.commit().unwrap();
 }
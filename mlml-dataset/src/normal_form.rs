@@ -0,0 +1,395 @@
+use crate::expr::{BinaryOpType, Expr};
+
+impl Expr {
+    /// Converts the expression to negation normal form: only ¬, ∧ and ∨ remain, and negations are
+    /// only applied to variables.
+    pub fn to_nnf(&self) -> Expr {
+        self.nnf(false).flatten()
+    }
+
+    fn nnf(&self, negated: bool) -> Expr {
+        use BinaryOpType::*;
+
+        match self {
+            Expr::Var(_) if negated => Expr::Not(Box::new(self.clone())),
+            Expr::Var(_) => self.clone(),
+            Expr::Const(b) => Expr::Const(*b != negated),
+            Expr::Not(e) => e.nnf(!negated),
+            Expr::BinaryOp(bop) => {
+                let (l, r) = (&bop.l, &bop.r);
+                // ↑ and ↓ are negated ∧ and ∨
+                let (ty, negated) = match bop.ty {
+                    Nand => (And, !negated),
+                    Nor => (Or, !negated),
+                    ty => (ty, negated),
+                };
+
+                match ty {
+                    // De Morgan's laws
+                    And | Or if (ty == And) != negated => {
+                        Expr::conjunction(vec![l.nnf(negated), r.nnf(negated)])
+                    }
+                    And | Or => Expr::disjunction(vec![l.nnf(negated), r.nnf(negated)]),
+                    // l → r is ¬l ∨ r
+                    Implies if negated => Expr::conjunction(vec![l.nnf(false), r.nnf(true)]),
+                    Implies => Expr::disjunction(vec![l.nnf(true), r.nnf(false)]),
+                    // l ↔ r is (l ∧ r) ∨ (¬l ∧ ¬r), and l ⊕ r is its negation
+                    Equivalent | Xor => {
+                        let equivalent = (ty == Equivalent) != negated;
+                        Expr::disjunction(vec![
+                            Expr::conjunction(vec![l.nnf(false), r.nnf(!equivalent)]),
+                            Expr::conjunction(vec![l.nnf(true), r.nnf(equivalent)]),
+                        ])
+                    }
+                    Nand | Nor => unreachable!(),
+                }
+            }
+            Expr::And(es) | Expr::Or(es) => {
+                let operands = es.iter().map(|e| e.nnf(negated)).collect();
+                if matches!(self, Expr::And(_)) != negated {
+                    Expr::conjunction(operands)
+                } else {
+                    Expr::disjunction(operands)
+                }
+            }
+        }
+    }
+
+    /// Converts the expression to conjunctive normal form by distributing ∨ over ∧; the result is
+    /// equivalent, but may be exponentially larger.
+    pub fn to_cnf(&self) -> Expr {
+        let clauses = remove_subsumed(self.to_nnf().clauses(BinaryOpType::And));
+        Expr::conjunction(clauses.into_iter().map(Expr::disjunction).collect())
+    }
+
+    /// Converts the expression to disjunctive normal form by distributing ∧ over ∨; the result is
+    /// equivalent, but may be exponentially larger.
+    pub fn to_dnf(&self) -> Expr {
+        let terms = remove_subsumed(self.to_nnf().clauses(BinaryOpType::Or));
+        Expr::disjunction(terms.into_iter().map(Expr::conjunction).collect())
+    }
+
+    /// Splits an NNF expression into groups of literals, with `outer` (∧ for CNF, ∨ for DNF)
+    /// joining the groups and the other junction joining the literals within them. Groups that
+    /// contain a literal and its negation are dropped, as are duplicate literals.
+    fn clauses(&self, outer: BinaryOpType) -> Vec<Vec<Expr>> {
+        let operands: Vec<&Expr> = match self {
+            Expr::BinaryOp(bop) => vec![&bop.l, &bop.r],
+            Expr::And(es) | Expr::Or(es) => es.iter().collect(),
+            // ⊤ is an empty CNF and a DNF with a single empty term; ⊥ is the opposite
+            Expr::Const(b) if *b == (outer == BinaryOpType::And) => return vec![],
+            Expr::Const(_) => return vec![vec![]],
+            _ => return vec![vec![self.clone()]],
+        };
+
+        if self.junction_type() == Some(outer) {
+            operands
+                .into_iter()
+                .flat_map(|e| e.clauses(outer))
+                .collect()
+        } else {
+            operands.into_iter().fold(vec![vec![]], |acc, e| {
+                let clauses = e.clauses(outer);
+                acc.iter()
+                    .flat_map(|a| {
+                        clauses.iter().filter_map(move |c| {
+                            let mut merged = a.clone();
+                            for lit in c {
+                                if merged.contains(&lit.negated()) {
+                                    return None;
+                                }
+                                if !merged.contains(lit) {
+                                    merged.push(lit.clone());
+                                }
+                            }
+                            Some(merged)
+                        })
+                    })
+                    .collect()
+            })
+        }
+    }
+
+    /// Converts the expression to conjunctive normal form using the Tseitin encoding, which
+    /// introduces a fresh variable for every connective. The result grows linearly with the size
+    /// of the expression and is equisatisfiable with it; more precisely, it is equivalent to the
+    /// expression once the fresh variables (named `t_0`, `t_1`, ..., skipping any names already in
+    /// use) are existentially quantified.
+    pub fn to_cnf_tseitin(&self) -> Expr {
+        Expr::conjunction(
+            self.tseitin_clauses()
+                .into_iter()
+                .map(Expr::disjunction)
+                .collect(),
+        )
+    }
+
+    /// Returns the clauses of the Tseitin encoding, each one a list of literals.
+    pub(crate) fn tseitin_clauses(&self) -> Vec<Vec<Expr>> {
+        let mut encoder = TseitinEncoder {
+            used: self.variables().into_iter().map(String::from).collect(),
+            next_id: 0,
+            clauses: Vec::new(),
+        };
+        let root = encoder.encode(self);
+        encoder.clauses.push(vec![root]);
+
+        encoder.clauses
+    }
+
+    /// Returns the negation of a literal, removing a double negation instead of adding one.
+    fn negated(&self) -> Expr {
+        match self {
+            Expr::Not(e) => (**e).clone(),
+            Expr::Const(b) => Expr::Const(!b),
+            _ => Expr::Not(Box::new(self.clone())),
+        }
+    }
+}
+
+/// Drops the groups of literals that contain all the literals of another group (and, among equal
+/// ones, all but the first), as they are redundant in both CNF and DNF.
+fn remove_subsumed(groups: Vec<Vec<Expr>>) -> Vec<Vec<Expr>> {
+    groups
+        .iter()
+        .enumerate()
+        .filter(|(i, g)| {
+            !groups.iter().enumerate().any(|(j, h)| {
+                j != *i && (h.len() < g.len() || j < *i) && h.iter().all(|lit| g.contains(lit))
+            })
+        })
+        .map(|(_, g)| g.clone())
+        .collect()
+}
+
+struct TseitinEncoder {
+    used: Vec<String>,
+    next_id: usize,
+    clauses: Vec<Vec<Expr>>,
+}
+
+impl TseitinEncoder {
+    fn fresh_var(&mut self) -> Expr {
+        loop {
+            let name = format!("t_{}", self.next_id);
+            self.next_id += 1;
+            if !self.used.contains(&name) {
+                return Expr::Var(name);
+            }
+        }
+    }
+
+    /// Returns a literal equivalent to the given expression, adding the clauses that define it.
+    fn encode(&mut self, expr: &Expr) -> Expr {
+        use BinaryOpType::*;
+
+        match expr {
+            Expr::Var(_) => expr.clone(),
+            Expr::Not(e) => self.encode(e).negated(),
+            Expr::Const(b) => {
+                let g = self.fresh_var();
+                self.clauses
+                    .push(vec![if *b { g.clone() } else { g.negated() }]);
+                g
+            }
+            Expr::BinaryOp(bop) => {
+                let (a, b) = (self.encode(&bop.l), self.encode(&bop.r));
+                let g = self.fresh_var();
+                // the remaining connectives are expressed through ∧, ∨ and ↔ by negating the
+                // operands or the gate itself
+                match bop.ty {
+                    And => self.define_junction(And, g.clone(), vec![a, b]),
+                    Or => self.define_junction(Or, g.clone(), vec![a, b]),
+                    Implies => self.define_junction(Or, g.clone(), vec![a.negated(), b]),
+                    Nand => self.define_junction(And, g.negated(), vec![a, b]),
+                    Nor => self.define_junction(Or, g.negated(), vec![a, b]),
+                    Equivalent => self.define_equivalence(g.clone(), a, b),
+                    Xor => self.define_equivalence(g.negated(), a, b),
+                }
+                g
+            }
+            Expr::And(es) | Expr::Or(es) => {
+                let operands = es.iter().map(|e| self.encode(e)).collect();
+                let g = self.fresh_var();
+                self.define_junction(expr.junction_type().unwrap(), g.clone(), operands);
+                g
+            }
+        }
+    }
+
+    /// Adds the clauses of `g ↔ (a1 ∧ ... ∧ an)` or `g ↔ (a1 ∨ ... ∨ an)`.
+    fn define_junction(&mut self, ty: BinaryOpType, g: Expr, operands: Vec<Expr>) {
+        // ∨ is the dual of ∧: g ↔ ∨ai is ¬g ↔ ∧¬ai
+        let (g, operands) = if ty == BinaryOpType::And {
+            (g, operands)
+        } else {
+            (g.negated(), operands.iter().map(|a| a.negated()).collect())
+        };
+
+        let mut long_clause = vec![g.clone()];
+        for a in operands {
+            long_clause.push(a.negated());
+            self.clauses.push(vec![g.negated(), a]);
+        }
+        self.clauses.push(long_clause);
+    }
+
+    /// Adds the clauses of `g ↔ (a ↔ b)`.
+    fn define_equivalence(&mut self, g: Expr, a: Expr, b: Expr) {
+        self.clauses.extend([
+            vec![g.negated(), a.negated(), b.clone()],
+            vec![g.negated(), a.clone(), b.negated()],
+            vec![g.clone(), a.clone(), b.clone()],
+            vec![g, a.negated(), b.negated()],
+        ]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::SeedableRng;
+    use rand_xorshift::XorShiftRng;
+
+    use super::*;
+    use crate::{
+        analysis::TruthTable,
+        generator::{ExprGenerator, variable_names},
+        parser::Parser,
+    };
+
+    fn is_literal(expr: &Expr) -> bool {
+        match expr {
+            Expr::Var(_) | Expr::Const(_) => true,
+            Expr::Not(e) => matches!(**e, Expr::Var(_)),
+            _ => false,
+        }
+    }
+
+    fn operands(expr: &Expr, ty: BinaryOpType) -> Vec<&Expr> {
+        match expr {
+            Expr::BinaryOp(bop) if bop.ty == ty => vec![&bop.l, &bop.r],
+            Expr::And(es) if ty == BinaryOpType::And => es.iter().collect(),
+            Expr::Or(es) if ty == BinaryOpType::Or => es.iter().collect(),
+            _ => vec![expr],
+        }
+    }
+
+    fn is_nnf(expr: &Expr) -> bool {
+        match expr {
+            Expr::BinaryOp(bop) if matches!(bop.ty, BinaryOpType::And | BinaryOpType::Or) => {
+                is_nnf(&bop.l) && is_nnf(&bop.r)
+            }
+            Expr::And(es) | Expr::Or(es) => es.iter().all(is_nnf),
+            _ => is_literal(expr),
+        }
+    }
+
+    fn is_normal_form(expr: &Expr, outer: BinaryOpType, inner: BinaryOpType) -> bool {
+        operands(expr, outer)
+            .into_iter()
+            .all(|e| operands(e, inner).into_iter().all(is_literal))
+    }
+
+    fn random_exprs() -> Vec<Expr> {
+        let mut rng = XorShiftRng::seed_from_u64(1);
+        let generator = ExprGenerator::new(4, 4)
+            .with_const_probability(0.1)
+            .with_max_arity(3);
+        let range = variable_names(4);
+
+        (0..300)
+            .map(|_| generator.generate(&range, &mut rng))
+            .collect()
+    }
+
+    /// Checks that the two expressions have the same truth table; the expected one must contain
+    /// all the variables of the actual one.
+    fn assert_equivalent(expected: &Expr, actual: &Expr) {
+        let table = TruthTable::new(expected);
+        for (state, result) in table.rows() {
+            assert_eq!(
+                actual.evaluate(state.bindings()),
+                result,
+                "{expected} vs {actual} under {state}"
+            );
+        }
+    }
+
+    #[test]
+    fn nnf() {
+        let expr = Parser::new("¬(a → (b ↑ ¬c))").parse().unwrap();
+        assert_eq!(expr.to_nnf().to_string(), "a ∧ b ∧ ¬c");
+
+        for expr in random_exprs() {
+            let nnf = expr.to_nnf();
+            assert!(is_nnf(&nnf), "{nnf}");
+            assert_equivalent(&expr, &nnf);
+        }
+    }
+
+    #[test]
+    fn cnf_and_dnf() {
+        let expr = Parser::new("(a ∧ b) ∨ (c ∧ d)").parse().unwrap();
+        assert_eq!(
+            expr.to_cnf().to_string(),
+            "(a ∨ c) ∧ (a ∨ d) ∧ (b ∨ c) ∧ (b ∨ d)"
+        );
+        assert_eq!(expr.to_cnf().to_dnf().to_string(), "(a ∧ b) ∨ (c ∧ d)");
+        assert_eq!(
+            Parser::new("p ∨ ¬p").parse().unwrap().to_cnf(),
+            Expr::Const(true)
+        );
+
+        for expr in random_exprs() {
+            let cnf = expr.to_cnf();
+            assert!(
+                is_normal_form(&cnf, BinaryOpType::And, BinaryOpType::Or),
+                "{cnf}"
+            );
+            assert_equivalent(&expr, &cnf);
+
+            let dnf = expr.to_dnf();
+            assert!(
+                is_normal_form(&dnf, BinaryOpType::Or, BinaryOpType::And),
+                "{dnf}"
+            );
+            assert_equivalent(&expr, &dnf);
+        }
+    }
+
+    #[test]
+    fn tseitin() {
+        let expr = Parser::new("t_0 ∧ ¬t_1").parse().unwrap();
+        assert_eq!(
+            expr.to_cnf_tseitin().to_string(),
+            "(¬t_2 ∨ t_0) ∧ (¬t_2 ∨ ¬t_1) ∧ (t_2 ∨ ¬t_0 ∨ t_1) ∧ t_2"
+        );
+
+        // the encoding of each connective introduces a variable, so keep the expressions small
+        let mut rng = XorShiftRng::seed_from_u64(2);
+        let generator = ExprGenerator::new(2, 3)
+            .with_const_probability(0.1)
+            .with_max_arity(3);
+        let range = variable_names(3);
+
+        for _ in 0..300 {
+            let expr = generator.generate(&range, &mut rng);
+            let cnf = expr.to_cnf_tseitin();
+            assert!(
+                is_normal_form(&cnf, BinaryOpType::And, BinaryOpType::Or),
+                "{cnf}"
+            );
+
+            // the original expression is true exactly when some values of the fresh variables
+            // satisfy the encoding
+            let table = TruthTable::new(&cnf);
+            let vars = expr.variables();
+            for (state, result) in TruthTable::new(&expr).rows() {
+                let satisfiable = table.rows().any(|(cnf_state, cnf_result)| {
+                    cnf_result && vars.iter().all(|v| cnf_state.get(v) == state.get(v))
+                });
+                assert_eq!(satisfiable, result, "{expr} vs {cnf} under {state}");
+            }
+        }
+    }
+}
@@ -1,68 +1,395 @@
-use std::iter::Peekable;
+use std::{fmt, iter::Peekable, str::CharIndices};
 
-use crate::expr::{BinaryOp, BinaryOpType, Expr};
+use crate::expr::{BinaryOp, BinaryOpType, Expr, NOT_ALIASES};
 
+/// Words that can't be used as variable names.
+const KEYWORDS: &[&str] = &["true", "false"];
+
+fn is_ident_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+/// A location in the parsed input, both as a byte offset and as a char offset.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Position {
+    pub byte: usize,
+    pub char: usize,
+}
+
+/// Something the parser was looking for when it encountered an error.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Expected {
+    Char(char),
+    Variable,
+    Constant,
+    Operator,
+    Value,
+    Eof,
+}
+
+impl fmt::Display for Expected {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Expected::Char(c) => write!(f, "'{c}'"),
+            Expected::Variable => write!(f, "variable"),
+            Expected::Constant => write!(f, "constant"),
+            Expected::Operator => write!(f, "operator"),
+            Expected::Value => write!(f, "'true' or 'false'"),
+            Expected::Eof => write!(f, "EOF"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseError {
+    /// A character other than any of the expected ones was found.
+    UnexpectedChar {
+        pos: Position,
+        expected: Vec<Expected>,
+        found: char,
+    },
+    /// The input ended while more of it was expected.
+    UnexpectedEof {
+        pos: Position,
+        expected: Vec<Expected>,
+    },
+    /// A state value other than `true` or `false` was found.
+    InvalidValue { pos: Position, found: String },
+    /// A keyword was used as a variable name.
+    ReservedWord { pos: Position, found: String },
+}
+
+impl ParseError {
+    pub fn pos(&self) -> Position {
+        match self {
+            ParseError::UnexpectedChar { pos, .. }
+            | ParseError::UnexpectedEof { pos, .. }
+            | ParseError::InvalidValue { pos, .. }
+            | ParseError::ReservedWord { pos, .. } => *pos,
+        }
+    }
+
+    /// Renders the error message followed by the line of the input it occurred in, with a caret
+    /// pointing at the offending character.
+    pub fn render(&self, input: &str) -> String {
+        let byte = self.pos().byte.min(input.len());
+        let line_start = input[..byte].rfind('\n').map(|i| i + 1).unwrap_or(0);
+        let line_end = input[byte..]
+            .find('\n')
+            .map(|i| byte + i)
+            .unwrap_or(input.len());
+        let column = input[line_start..byte].chars().count();
+
+        format!(
+            "{self}\n{}\n{}^",
+            &input[line_start..line_end],
+            " ".repeat(column)
+        )
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let write_expected = |f: &mut fmt::Formatter<'_>, expected: &[Expected]| {
+            for (i, e) in expected.iter().enumerate() {
+                if i > 0 {
+                    let sep = if i + 1 == expected.len() {
+                        " or "
+                    } else {
+                        ", "
+                    };
+                    write!(f, "{sep}")?;
+                }
+                write!(f, "{e}")?;
+            }
+            Ok(())
+        };
+
+        match self {
+            ParseError::UnexpectedChar {
+                pos,
+                expected,
+                found,
+            } => {
+                write!(f, "Expected ")?;
+                write_expected(f, expected)?;
+                write!(f, ", got '{found}' at position {}", pos.char)
+            }
+            ParseError::UnexpectedEof { pos, expected } => {
+                write!(f, "Expected ")?;
+                write_expected(f, expected)?;
+                write!(f, ", got EOF at position {}", pos.char)
+            }
+            ParseError::InvalidValue { pos, found } => {
+                write!(
+                    f,
+                    "Expected 'true' or 'false', got '{found}' at position {}",
+                    pos.char
+                )
+            }
+            ParseError::ReservedWord { pos, found } => {
+                write!(
+                    f,
+                    "Expected variable, got keyword '{found}' at position {}",
+                    pos.char
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// A precedence-climbing parser for expressions; binary operations bind (from the tightest) in
+/// the order ∧ and ↑, ⊕, ∨ and ↓, →, ↔, with → being right-associative and the rest
+/// left-associative. Negation binds tighter than any binary operator, and parentheses are optional.
+/// Every operator may also be written using its ASCII aliases, e.g. `!a & (b -> c)`, and the
+/// constants ⊤ and ⊥ as `true` and `false`.
 pub struct Parser<'a> {
-    chars: Peekable<std::str::Chars<'a>>,
+    input: &'a str,
+    chars: Peekable<CharIndices<'a>>,
+    char_pos: usize,
 }
 
 impl<'a> Parser<'a> {
     pub fn new(input: &'a str) -> Self {
         Parser {
-            chars: input.chars().peekable(),
+            input,
+            chars: input.char_indices().peekable(),
+            char_pos: 0,
         }
     }
 
-    pub fn parse(&mut self) -> Result<Expr, String> {
-        self.parse_expr()
+    pub fn parse(&mut self) -> Result<Expr, ParseError> {
+        let expr = self.parse_expr(0)?;
+        self.expect_eof()?;
+
+        Ok(expr)
     }
 
-    fn parse_expr(&mut self) -> Result<Expr, String> {
+    pub fn expect_eof(&mut self) -> Result<(), ParseError> {
         self.skip_whitespace();
         match self.peek() {
-            Some('(') => self.parse_binary(),
-            Some('¬') => self.parse_not(),
-            Some(c) if c.is_alphabetic() => self.parse_var(),
-            _ => Err("Expected variable, '(', or '¬'".to_string()),
+            None => Ok(()),
+            Some(_) => Err(self.unexpected(&[Expected::Operator, Expected::Eof])),
         }
     }
 
-    fn parse_binary(&mut self) -> Result<Expr, String> {
-        self.expect('(')?;
-        let left = self.parse_expr()?;
+    /// Parses a state prefix such as `[a, b: true; c: false]` into its raw variable bindings, in
+    /// the order in which they appear. The bindings are not checked for duplicates or conflicts.
+    pub fn parse_state(&mut self) -> Result<Vec<(String, bool)>, ParseError> {
         self.skip_whitespace();
-        let op = self.next_char()?.ok_or("Expected operator".to_string())?;
-        let right = self.parse_expr()?;
+        self.expect('[')?;
+        let mut bindings = Vec::new();
+
+        self.skip_whitespace();
+        if self.peek() == Some(']') {
+            self.next_char();
+            return Ok(bindings);
+        }
+
+        loop {
+            let mut vars = Vec::new();
+            loop {
+                self.skip_whitespace();
+                vars.push(self.parse_var_name()?);
+                self.skip_whitespace();
+                match self.peek() {
+                    Some(',') => self.next_char(),
+                    Some(':') => {
+                        self.next_char();
+                        break;
+                    }
+                    _ => return Err(self.unexpected(&[Expected::Char(','), Expected::Char(':')])),
+                };
+            }
+
+            self.skip_whitespace();
+            let value = self.parse_value()?;
+            bindings.extend(vars.into_iter().map(|var| (var, value)));
+
+            self.skip_whitespace();
+            match self.peek() {
+                Some(';') => self.next_char(),
+                Some(']') => {
+                    self.next_char();
+                    break;
+                }
+                _ => return Err(self.unexpected(&[Expected::Char(';'), Expected::Char(']')])),
+            };
+        }
+
+        Ok(bindings)
+    }
+
+    fn parse_expr(&mut self, min_precedence: u8) -> Result<Expr, ParseError> {
+        let mut left = self.parse_unary()?;
+
+        loop {
+            self.skip_whitespace();
+            let Some((op, symbol)) = self.peek_binary_op() else {
+                break;
+            };
+            let precedence = op.precedence();
+            if precedence < min_precedence {
+                break;
+            }
+            self.consume(symbol);
+
+            let next_min_precedence = if op.is_right_associative() {
+                precedence
+            } else {
+                precedence + 1
+            };
+            let right = self.parse_expr(next_min_precedence)?;
+
+            if !matches!(op, BinaryOpType::And | BinaryOpType::Or) {
+                left = Expr::BinaryOp(Box::new(BinaryOp::new(op, left, right)));
+                continue;
+            }
+
+            // unparenthesized chains of ∧ or ∨ become a single n-ary node
+            let mut operands = vec![left, right];
+            loop {
+                self.skip_whitespace();
+                match self.peek_binary_op() {
+                    Some((next_op, symbol)) if next_op == op => {
+                        self.consume(symbol);
+                        operands.push(self.parse_expr(next_min_precedence)?);
+                    }
+                    _ => break,
+                }
+            }
+            left = if op == BinaryOpType::And {
+                Expr::conjunction(operands)
+            } else {
+                Expr::disjunction(operands)
+            };
+        }
+
+        Ok(left)
+    }
+
+    fn parse_unary(&mut self) -> Result<Expr, ParseError> {
         self.skip_whitespace();
-        self.expect(')')?;
+        if let Some(symbol) = NOT_ALIASES.iter().find(|s| self.peek_str(s)) {
+            self.consume(symbol);
+            let expr = self.parse_unary()?;
+            return Ok(Expr::Not(Box::new(expr)));
+        }
+
+        // the symbols need no word boundary, unlike the keywords
+        for (value, symbol, keyword) in [(true, "⊤", "true"), (false, "⊥", "false")] {
+            if self.peek_str(symbol) {
+                self.consume(symbol);
+                return Ok(Expr::Const(value));
+            }
+            if self.peek_keyword(keyword) {
+                self.consume(keyword);
+                return Ok(Expr::Const(value));
+            }
+        }
 
-        let op = match op {
-            '∧' => BinaryOpType::And,
-            '∨' => BinaryOpType::Or,
-            '→' => BinaryOpType::Implies,
-            '↔' => BinaryOpType::Equivalent,
+        match self.peek() {
+            Some('(') => self.parse_parenthesized(),
+            Some(c) if c.is_alphabetic() => self.parse_var(),
             _ => {
-                return Err(format!("Unknown operator: {op}"));
+                let mut expected =
+                    vec![Expected::Variable, Expected::Constant, Expected::Char('(')];
+                expected.extend(
+                    NOT_ALIASES
+                        .iter()
+                        .flat_map(|s| s.chars().next())
+                        .map(Expected::Char),
+                );
+                Err(self.unexpected(&expected))
             }
+        }
+    }
+
+    fn parse_parenthesized(&mut self) -> Result<Expr, ParseError> {
+        self.expect('(')?;
+        let expr = self.parse_expr(0)?;
+        self.skip_whitespace();
+        match self.peek() {
+            Some(')') => self.next_char(),
+            _ => return Err(self.unexpected(&[Expected::Operator, Expected::Char(')')])),
         };
 
-        Ok(Expr::BinaryOp(Box::new(BinaryOp::new(op, left, right))))
+        Ok(expr)
+    }
+
+    fn parse_var(&mut self) -> Result<Expr, ParseError> {
+        self.parse_var_name().map(Expr::Var)
     }
 
-    fn parse_not(&mut self) -> Result<Expr, String> {
-        self.expect('¬')?;
-        let expr = self.parse_expr()?;
-        Ok(Expr::Not(Box::new(expr)))
+    /// Parses an identifier: a letter followed by any number of letters, digits and underscores.
+    fn parse_var_name(&mut self) -> Result<String, ParseError> {
+        if !self.peek().is_some_and(char::is_alphabetic) {
+            return Err(self.unexpected(&[Expected::Variable]));
+        }
+
+        let pos = self.pos();
+        let name = self.read_word();
+        if KEYWORDS.contains(&name.as_str()) {
+            return Err(ParseError::ReservedWord { pos, found: name });
+        }
+
+        Ok(name)
     }
 
-    fn parse_var(&mut self) -> Result<Expr, String> {
-        if let Some(c) = self.next_char()? {
-            if !c.is_alphabetic() {
-                return Err("Variable must start with a letter".to_string());
+    fn parse_value(&mut self) -> Result<bool, ParseError> {
+        let pos = self.pos();
+        let word = self.read_word();
+
+        match word.as_str() {
+            "true" => Ok(true),
+            "false" => Ok(false),
+            "" => Err(self.unexpected(&[Expected::Value])),
+            _ => Err(ParseError::InvalidValue { pos, found: word }),
+        }
+    }
+
+    fn read_word(&mut self) -> String {
+        let mut word = String::new();
+        while let Some(c) = self.peek() {
+            if !is_ident_char(c) {
+                break;
             }
-            Ok(Expr::Var(c))
-        } else {
-            unreachable!();
+            word.push(c);
+            self.next_char();
+        }
+
+        word
+    }
+
+    /// Returns the binary operator at the current position along with its spelling, preferring the
+    /// longest matching alias (e.g. `&&` over `&`).
+    fn peek_binary_op(&mut self) -> Option<(BinaryOpType, &'static str)> {
+        BinaryOpType::ALL
+            .iter()
+            .flat_map(|op| op.aliases().iter().map(move |s| (*op, *s)))
+            .filter(|(_, s)| self.peek_str(s))
+            .max_by_key(|(_, s)| s.len())
+    }
+
+    fn peek_str(&mut self, s: &str) -> bool {
+        let byte = self.pos().byte;
+        self.input[byte..].starts_with(s)
+    }
+
+    /// Like `peek_str`, but the match must not be immediately followed by an identifier character,
+    /// so that e.g. `true` isn't found at the beginning of `true_1`.
+    fn peek_keyword(&mut self, s: &str) -> bool {
+        let byte = self.pos().byte;
+        self.input[byte..]
+            .strip_prefix(s)
+            .is_some_and(|rest| !rest.starts_with(is_ident_char))
+    }
+
+    fn consume(&mut self, s: &str) {
+        for _ in s.chars() {
+            self.next_char();
         }
     }
 
@@ -71,23 +398,287 @@ impl<'a> Parser<'a> {
             if !c.is_whitespace() {
                 break;
             }
-            let _ = self.next_char();
+            self.next_char();
+        }
+    }
+
+    fn pos(&mut self) -> Position {
+        let byte = self
+            .chars
+            .peek()
+            .map(|(i, _)| *i)
+            .unwrap_or(self.input.len());
+
+        Position {
+            byte,
+            char: self.char_pos,
         }
     }
 
     fn peek(&mut self) -> Option<char> {
-        self.chars.peek().copied()
+        self.chars.peek().map(|(_, c)| *c)
     }
 
-    fn next_char(&mut self) -> Result<Option<char>, String> {
-        Ok(self.chars.next())
+    fn next_char(&mut self) -> Option<char> {
+        let c = self.chars.next().map(|(_, c)| c);
+        if c.is_some() {
+            self.char_pos += 1;
+        }
+        c
+    }
+
+    /// Creates an error describing the character at the current position.
+    fn unexpected(&mut self, expected: &[Expected]) -> ParseError {
+        let pos = self.pos();
+        let expected = expected.to_vec();
+        match self.peek() {
+            Some(found) => ParseError::UnexpectedChar {
+                pos,
+                expected,
+                found,
+            },
+            None => ParseError::UnexpectedEof { pos, expected },
+        }
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), ParseError> {
+        match self.peek() {
+            Some(c) if c == expected => {
+                self.next_char();
+                Ok(())
+            }
+            _ => Err(self.unexpected(&[Expected::Char(expected)])),
+        }
     }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::SeedableRng;
+    use rand_xorshift::XorShiftRng;
+
+    use super::*;
+    use crate::{
+        expr::Notation,
+        generator::{ExprGenerator, variable_names},
+    };
+
+    fn var(v: &str) -> Expr {
+        Expr::Var(v.to_owned())
+    }
+
+    fn bop(ty: BinaryOpType, l: Expr, r: Expr) -> Expr {
+        Expr::BinaryOp(Box::new(BinaryOp::new(ty, l, r)))
+    }
+
+    #[test]
+    fn precedence() {
+        use BinaryOpType::*;
+
+        let expr = Parser::new("a ∨ b ∧ ¬c → d ↔ e").parse().unwrap();
+        let expected = bop(
+            Equivalent,
+            bop(
+                Implies,
+                bop(
+                    Or,
+                    var("a"),
+                    bop(And, var("b"), Expr::Not(Box::new(var("c")))),
+                ),
+                var("d"),
+            ),
+            var("e"),
+        );
+        assert_eq!(expr, expected);
+    }
+
+    #[test]
+    fn extended_connectives() {
+        use BinaryOpType::*;
+
+        let expr = Parser::new("a ↓ b ⊕ c ↑ d").parse().unwrap();
+        assert_eq!(
+            expr,
+            bop(
+                Nor,
+                var("a"),
+                bop(Xor, var("b"), bop(Nand, var("c"), var("d")))
+            )
+        );
+
+        for (ty, table) in [
+            (Xor, [false, true, true, false]),
+            (Nand, [true, true, true, false]),
+            (Nor, [true, false, false, false]),
+        ] {
+            let expr = bop(ty, var("a"), var("b"));
+            for (i, (a, b)) in [(false, false), (false, true), (true, false), (true, true)]
+                .into_iter()
+                .enumerate()
+            {
+                assert_eq!(expr.evaluate(&[("a".into(), a), ("b".into(), b)]), table[i]);
+            }
+        }
+    }
+
+    #[test]
+    fn identifiers() {
+        use BinaryOpType::*;
+
+        let expr = Parser::new("rain ∧ ¬x1 → p_10").parse().unwrap();
+        assert_eq!(
+            expr,
+            bop(
+                Implies,
+                bop(And, var("rain"), Expr::Not(Box::new(var("x1")))),
+                var("p_10")
+            )
+        );
+        assert_eq!(
+            Parser::new("true_1 ∨ falsey").parse(),
+            Ok(bop(Or, var("true_1"), var("falsey")))
+        );
+
+        let err = Parser::new("[true: false] a").parse_state().unwrap_err();
+        assert_eq!(
+            err,
+            ParseError::ReservedWord {
+                pos: Position { byte: 1, char: 1 },
+                found: "true".into()
+            }
+        );
+    }
+
+    #[test]
+    fn constants() {
+        use BinaryOpType::*;
+
+        let expr = Parser::new("p ∨ ⊥").parse().unwrap();
+        assert_eq!(expr, bop(Or, var("p"), Expr::Const(false)));
+        let expr = Parser::new("(q → true) ∧ ¬false").parse().unwrap();
+        assert_eq!(
+            expr,
+            bop(
+                And,
+                bop(Implies, var("q"), Expr::Const(true)),
+                Expr::Not(Box::new(Expr::Const(false)))
+            )
+        );
+        assert_eq!(expr.to_string(), "(q → ⊤) ∧ ¬⊥");
+        assert_eq!(expr.to_string_with(Notation::Ascii), "(q -> true) & !false");
+    }
+
+    #[test]
+    fn associativity() {
+        use BinaryOpType::*;
+
+        let expr = Parser::new("a → b → c").parse().unwrap();
+        assert_eq!(
+            expr,
+            bop(Implies, var("a"), bop(Implies, var("b"), var("c")))
+        );
+
+        let expr = Parser::new("a ↔ b ↔ c").parse().unwrap();
+        assert_eq!(
+            expr,
+            bop(Equivalent, bop(Equivalent, var("a"), var("b")), var("c"))
+        );
+    }
+
+    #[test]
+    fn nary() {
+        use BinaryOpType::*;
+
+        let expr = Parser::new("a ∧ b ∧ c").parse().unwrap();
+        assert_eq!(expr, Expr::And(vec![var("a"), var("b"), var("c")]));
+        let expr = Parser::new("(a ∧ b) ∧ c").parse().unwrap();
+        assert_eq!(expr, bop(And, bop(And, var("a"), var("b")), var("c")));
+
+        let cnf = "(a ∨ ¬b ∨ c) ∧ (¬a ∨ d) ∧ (b ∨ c ∨ d ∨ e)";
+        let expr = Parser::new(cnf).parse().unwrap();
+        assert_eq!(expr.to_string(), cnf);
+        assert_eq!(expr.depth(), 6);
+
+        let nested = Parser::new("((a ∧ b) ∧ (c ∧ d)) ∨ (e ∨ (f ∨ g))")
+            .parse()
+            .unwrap();
+        assert_eq!(nested.flatten().to_string(), "(a ∧ b ∧ c ∧ d) ∨ e ∨ f ∨ g");
+    }
+
+    #[test]
+    fn malformed() {
+        for input in ["", "a ∧", "(a ∨ b", "a b", "¬", "a ∧ )"] {
+            assert!(Parser::new(input).parse().is_err(), "{input}");
+        }
+    }
+
+    #[test]
+    fn ascii_aliases() {
+        for (ascii, unicode) in [
+            ("!a & b", "¬a ∧ b"),
+            ("~a && (b | c)", "¬a ∧ (b ∨ c)"),
+            ("a || b -> c", "(a ∨ b) → c"),
+            ("a => b <-> c <=> !d", "((a → b) ↔ c) ↔ ¬d"),
+            ("a ^ b !& c !| d", "(a ⊕ (b ↑ c)) ↓ d"),
+        ] {
+            let expr = Parser::new(ascii).parse().unwrap();
+            assert_eq!(expr.to_string(), unicode);
+            assert_eq!(
+                Parser::new(&expr.to_string_with(Notation::Ascii)).parse(),
+                Ok(expr)
+            );
+        }
+    }
+
+    #[test]
+    fn error_position() {
+        let input = "(a ∧ ¬b) c";
+        let err = Parser::new(input).parse().unwrap_err();
+        assert_eq!(
+            err,
+            ParseError::UnexpectedChar {
+                pos: Position { byte: 12, char: 9 },
+                expected: vec![Expected::Operator, Expected::Eof],
+                found: 'c',
+            }
+        );
+        assert_eq!(
+            err.render(input),
+            "Expected operator or EOF, got 'c' at position 9\n(a ∧ ¬b) c\n         ^"
+        );
+
+        let err = Parser::new("a ∧ (b").parse().unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Expected operator or ')', got EOF at position 6"
+        );
+
+        let err = Parser::new("a ∧ )").parse().unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Expected variable, constant, '(', '¬', '!' or '~', got ')' at position 4"
+        );
+
+        // the constant symbols are followed by an unexpected variable, not unexpected themselves
+        for input in ["⊤a", "⊥x"] {
+            let err = Parser::new(input).parse().unwrap_err();
+            assert!(
+                matches!(err, ParseError::UnexpectedChar { pos, .. } if pos.char == 1),
+                "{err}"
+            );
+        }
+    }
+
+    #[test]
+    fn display_round_trip() {
+        let mut rng = XorShiftRng::seed_from_u64(0);
+        let generator = ExprGenerator::new(4, 5).with_max_arity(4);
+        let range = variable_names(30);
 
-    fn expect(&mut self, expected: char) -> Result<(), String> {
-        match self.next_char()? {
-            Some(c) if c == expected => Ok(()),
-            Some(c) => Err(format!("Expected '{expected}', got '{c}'")),
-            None => Err(format!("Expected '{expected}', got EOF")),
+        for _ in 0..1000 {
+            let expr = generator.generate(&range, &mut rng);
+            let expr_str = expr.to_string();
+            assert_eq!(Parser::new(&expr_str).parse(), Ok(expr), "{expr_str}");
         }
     }
 }
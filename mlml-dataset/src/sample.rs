@@ -0,0 +1,298 @@
+use std::{fmt, str::FromStr};
+
+use crate::{
+    expr::{Expr, canonical_names},
+    parser::{ParseError, Parser},
+};
+
+/// An error encountered while parsing or validating a sample.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SampleError {
+    Syntax(ParseError),
+    DuplicateBinding(String),
+    ConflictingBinding(String),
+    MissingBinding(String),
+}
+
+impl fmt::Display for SampleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SampleError::Syntax(e) => write!(f, "{e}"),
+            SampleError::DuplicateBinding(v) => write!(f, "Variable '{v}' is bound more than once"),
+            SampleError::ConflictingBinding(v) => {
+                write!(f, "Variable '{v}' is bound to both true and false")
+            }
+            SampleError::MissingBinding(v) => write!(f, "Variable '{v}' is not bound"),
+        }
+    }
+}
+
+impl std::error::Error for SampleError {}
+
+impl From<ParseError> for SampleError {
+    fn from(e: ParseError) -> Self {
+        SampleError::Syntax(e)
+    }
+}
+
+/// A set of unique variable bindings; the `[a, b: true; c: false]` prefix of a sample.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Assignment {
+    bindings: Box<[(String, bool)]>,
+}
+
+impl Assignment {
+    /// Creates an assignment from the given bindings, rejecting any variable bound more than once.
+    /// The bindings are reordered to match the displayed form, i.e. all the true ones come first.
+    pub fn new(bindings: impl IntoIterator<Item = (String, bool)>) -> Result<Self, SampleError> {
+        let (mut ts, mut fs): (Vec<(String, bool)>, Vec<_>) = (Vec::new(), Vec::new());
+        for (v, b) in bindings {
+            if let Some((_, prev)) = ts.iter().chain(fs.iter()).find(|(v2, _)| *v2 == v) {
+                return Err(if *prev == b {
+                    SampleError::DuplicateBinding(v)
+                } else {
+                    SampleError::ConflictingBinding(v)
+                });
+            }
+            if b {
+                ts.push((v, b));
+            } else {
+                fs.push((v, b));
+            }
+        }
+        ts.extend(fs);
+
+        Ok(Self {
+            bindings: ts.into_boxed_slice(),
+        })
+    }
+
+    pub fn bindings(&self) -> &[(String, bool)] {
+        &self.bindings
+    }
+
+    pub fn get(&self, var: &str) -> Option<bool> {
+        self.bindings
+            .iter()
+            .find(|(v, _)| v == var)
+            .map(|(_, b)| *b)
+    }
+}
+
+impl fmt::Display for Assignment {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let ts = self.bindings.iter().filter(|(_, b)| *b).map(|(v, _)| v);
+        let fs = self.bindings.iter().filter(|(_, b)| !*b).map(|(v, _)| v);
+        let (ts, fs): (Vec<_>, Vec<_>) = (ts.collect(), fs.collect());
+
+        write!(f, "[")?;
+        for (vars, val) in [(&ts, "true"), (&fs, "false")] {
+            if !vars.is_empty() {
+                let mut iter = vars.iter().peekable();
+                while let Some(v) = iter.next() {
+                    write!(f, "{v}")?;
+                    if iter.peek().is_some() {
+                        write!(f, ", ")?;
+                    }
+                }
+                write!(f, ": {val}")?;
+                if !ts.is_empty() && !fs.is_empty() && val == "true" {
+                    write!(f, "; ")?;
+                }
+            }
+        }
+        write!(f, "]")
+    }
+}
+
+impl FromStr for Assignment {
+    type Err = SampleError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parser = Parser::new(s);
+        let bindings = parser.parse_state()?;
+        parser.expect_eof()?;
+
+        Self::new(bindings)
+    }
+}
+
+/// A full `[state] expr` line, in which every variable of the expression is bound by the state.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Sample {
+    pub state: Assignment,
+    pub expr: Expr,
+}
+
+impl Sample {
+    pub fn new(state: Assignment, expr: Expr) -> Result<Self, SampleError> {
+        if let Some(var) = expr
+            .variables()
+            .into_iter()
+            .find(|var| state.get(var).is_none())
+        {
+            return Err(SampleError::MissingBinding(var.to_owned()));
+        }
+
+        Ok(Self { state, expr })
+    }
+
+    pub fn evaluate(&self) -> bool {
+        self.expr.evaluate(self.state.bindings())
+    }
+
+    /// Renames the variables to `a`, `b`, `c`, ... in the order of their first occurrence in the
+    /// expression (followed by the ones only bound by the state), and lists the bindings in that
+    /// order; alpha-equivalent samples become equal.
+    pub fn canonical_renaming(&self) -> Sample {
+        let mut variables = self.expr.variables();
+        for (v, _) in self.state.bindings() {
+            if !variables.contains(&v.as_str()) {
+                variables.push(v);
+            }
+        }
+        let renaming = canonical_names(variables.iter().copied());
+
+        let bindings = variables
+            .iter()
+            .map(|v| (renaming[*v].clone(), self.state.get(v).unwrap()));
+        Sample {
+            state: Assignment::new(bindings).unwrap(),
+            expr: self.expr.rename_variables(&|v| renaming[v].clone()),
+        }
+    }
+}
+
+/// The number of tokens mlml-model's tokenizer encodes a sample into, i.e. the expression, preceded
+/// by the state if there is one, as they are displayed: `<cls>`, the state with an `<assign>`
+/// `</assign>` pair around each group of bindings and the values prefixed, and the expression
+/// wrapped in parentheses, with its operators prefixed.
+pub fn token_count(state: Option<&Assignment>, expr: &Expr) -> usize {
+    let state_count = state.map_or(0, |state| {
+        let ts = state.bindings.iter().filter(|(_, b)| *b).count();
+        let fs = state.bindings.len() - ts;
+        // `[` and `]`, then each group's variables, commas, `:`, its value and the 3 tags
+        2 + [ts, fs]
+            .into_iter()
+            .filter(|vars| *vars != 0)
+            .map(|vars| 2 * vars + 4)
+            .sum::<usize>()
+    });
+
+    1 + state_count + 2 + expr_token_count(expr, false)
+}
+
+fn expr_token_count(expr: &Expr, is_deep: bool) -> usize {
+    // the parentheses `Expr::_to_string` wraps nested operations in
+    let parens = if is_deep { 2 } else { 0 };
+    match expr {
+        Expr::Var(_) | Expr::Const(_) => 1,
+        Expr::Not(e) => 2 + expr_token_count(e, true),
+        Expr::BinaryOp(bop) => {
+            parens + 2 + expr_token_count(&bop.l, true) + expr_token_count(&bop.r, true)
+        }
+        Expr::And(es) | Expr::Or(es) => {
+            parens
+                + 2 * (es.len() - 1)
+                + es.iter().map(|e| expr_token_count(e, true)).sum::<usize>()
+        }
+    }
+}
+
+impl fmt::Display for Sample {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.state, self.expr)
+    }
+}
+
+impl FromStr for Sample {
+    type Err = SampleError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parser = Parser::new(s);
+        let bindings = parser.parse_state()?;
+        let expr = parser.parse()?;
+
+        Self::new(Assignment::new(bindings)?, expr)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        for line in [
+            "[s, p: false] s ∨ p",
+            "[m: true; z, f: false] (z ∧ m) → f",
+            "[n, v: true; d: false] (n ↔ v) ↔ (n → d)",
+            "[rain: true; x1, p_10: false] rain → (x1 ∨ ¬p_10)",
+        ] {
+            let sample: Sample = line.parse().unwrap();
+            assert_eq!(sample.to_string(), line);
+        }
+    }
+
+    #[test]
+    fn evaluation() {
+        let sample: Sample = "[m: true; z, f: false] (z ∧ m) → f".parse().unwrap();
+        assert!(sample.evaluate());
+        let sample: Sample = "[a: false; b: true] a ∨ ¬b".parse().unwrap();
+        assert_eq!(sample.to_string(), "[b: true; a: false] a ∨ ¬b");
+        assert!(!sample.evaluate());
+    }
+
+    #[test]
+    fn canonical_renaming() {
+        let canonical = |line: &str| line.parse::<Sample>().unwrap().canonical_renaming();
+
+        assert_eq!(
+            canonical("[q: true; x1, p: false] (x1 ∧ q) → ¬p").to_string(),
+            "[b: true; a, c: false] (a ∧ b) → ¬c"
+        );
+        assert_eq!(
+            canonical("[s, p: true] p ∨ s"),
+            canonical("[m: true; z: true] z ∨ m")
+        );
+        assert_ne!(
+            canonical("[s: true; p: false] p ∨ s"),
+            canonical("[s: false; p: true] p ∨ s")
+        );
+        assert_eq!(
+            canonical("[k: true; j: false] k").to_string(),
+            "[a: true; b: false] a"
+        );
+    }
+
+    #[test]
+    fn token_counts() {
+        let sample: Sample = "[m: true; z, f: false] (z ∧ m) → f".parse().unwrap();
+        // <cls> [ <assign> m : <value_prefix> true </assign> <assign> z , f : <value_prefix> false
+        // </assign> ] ( ( z <operator_prefix> ∧ m ) <operator_prefix> → f )
+        assert_eq!(token_count(Some(&sample.state), &sample.expr), 28);
+
+        let expr = Parser::new("¬(a ∧ b ∧ ⊤) ∨ c").parse().unwrap();
+        assert_eq!(token_count(None, &expr), 17);
+    }
+
+    #[test]
+    fn invalid_bindings() {
+        assert_eq!(
+            "[a, a: true] a".parse::<Sample>(),
+            Err(SampleError::DuplicateBinding("a".into()))
+        );
+        assert_eq!(
+            "[a: true; a: false] a".parse::<Sample>(),
+            Err(SampleError::ConflictingBinding("a".into()))
+        );
+        assert_eq!(
+            "[a: true] a ∧ b".parse::<Sample>(),
+            Err(SampleError::MissingBinding("b".into()))
+        );
+        assert!(matches!(
+            "[a: maybe] a".parse::<Sample>(),
+            Err(SampleError::Syntax(_))
+        ));
+    }
+}
@@ -0,0 +1,357 @@
+use std::fmt;
+
+use crate::expr::{BinaryOp, BinaryOpType, Expr};
+
+/// A rewrite rule applied by the `Simplifier`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Rule {
+    /// `¬¬a` → `a`
+    DoubleNegation,
+    /// `a ∧ a` → `a`, `a ∨ a` → `a`
+    Idempotence,
+    /// `a ∧ (a ∨ b)` → `a`, `a ∨ (a ∧ b)` → `a`
+    Absorption,
+    /// `a ∧ ¬a` → `⊥`, `a ∨ ¬a` → `⊤`
+    Complement,
+    /// `a → b` → `¬a ∨ b`
+    ImplicationElimination,
+    /// Removes constants, e.g. `a ∧ ⊤` → `a`, `a → ⊥` → `¬a`, `¬⊤` → `⊥`
+    ConstantFolding,
+    /// `¬(a ∧ b)` → `¬a ∨ ¬b`, `¬(a ∨ b)` → `¬a ∧ ¬b`
+    DeMorgan,
+}
+
+impl Rule {
+    pub const ALL: [Rule; 7] = [
+        Rule::DoubleNegation,
+        Rule::Idempotence,
+        Rule::Absorption,
+        Rule::Complement,
+        Rule::ImplicationElimination,
+        Rule::ConstantFolding,
+        Rule::DeMorgan,
+    ];
+
+    /// The rules that only ever shrink an expression; an expression that any of them applies to
+    /// contains a trivial redundancy.
+    pub const REDUCTIONS: [Rule; 5] = [
+        Rule::DoubleNegation,
+        Rule::Idempotence,
+        Rule::Absorption,
+        Rule::Complement,
+        Rule::ConstantFolding,
+    ];
+}
+
+impl fmt::Display for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Rule::DoubleNegation => "double negation",
+            Rule::Idempotence => "idempotence",
+            Rule::Absorption => "absorption",
+            Rule::Complement => "complement",
+            Rule::ImplicationElimination => "implication elimination",
+            Rule::ConstantFolding => "constant folding",
+            Rule::DeMorgan => "De Morgan",
+        };
+        write!(f, "{name}")
+    }
+}
+
+/// The result of a simplification.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Simplification {
+    pub expr: Expr,
+    /// The rules that were applied, in order; a rule applied several times is listed every time.
+    pub rules: Vec<Rule>,
+}
+
+/// Rewrites expressions bottom-up with the enabled rules until none of them applies anymore.
+#[derive(Debug, Clone)]
+pub struct Simplifier {
+    rules: Vec<Rule>,
+}
+
+impl Default for Simplifier {
+    fn default() -> Self {
+        Self::new(&Rule::ALL)
+    }
+}
+
+impl Simplifier {
+    pub fn new(rules: &[Rule]) -> Self {
+        Self {
+            rules: rules.to_vec(),
+        }
+    }
+
+    pub fn simplify(&self, expr: &Expr) -> Simplification {
+        let mut rules = Vec::new();
+        let expr = self.simplify_recurse(expr, &mut rules);
+
+        Simplification { expr, rules }
+    }
+
+    fn simplify_recurse(&self, expr: &Expr, fired: &mut Vec<Rule>) -> Expr {
+        let mut expr = match expr {
+            Expr::Var(_) | Expr::Const(_) => expr.clone(),
+            Expr::Not(e) => Expr::Not(Box::new(self.simplify_recurse(e, fired))),
+            Expr::BinaryOp(bop) => Expr::BinaryOp(Box::new(BinaryOp::new(
+                bop.ty,
+                self.simplify_recurse(&bop.l, fired),
+                self.simplify_recurse(&bop.r, fired),
+            ))),
+            Expr::And(es) => {
+                Expr::And(es.iter().map(|e| self.simplify_recurse(e, fired)).collect())
+            }
+            Expr::Or(es) => Expr::Or(es.iter().map(|e| self.simplify_recurse(e, fired)).collect()),
+        };
+
+        // a rewrite may create new subexpressions (e.g. De Morgan creates negations), which need to
+        // be simplified in turn
+        while let Some((rewritten, rule)) = self.rewrite(&expr) {
+            fired.push(rule);
+            expr = self.simplify_recurse(&rewritten, fired);
+        }
+
+        expr
+    }
+
+    fn enabled(&self, rule: Rule) -> bool {
+        self.rules.contains(&rule)
+    }
+
+    /// Applies the first applicable rule to the root of the expression.
+    fn rewrite(&self, expr: &Expr) -> Option<(Expr, Rule)> {
+        match expr {
+            Expr::Var(_) | Expr::Const(_) => None,
+            Expr::Not(e) => self.rewrite_not(e),
+            Expr::BinaryOp(bop) if expr.junction_type().is_none() => self.rewrite_binary(bop),
+            _ => self.rewrite_junction(expr),
+        }
+    }
+
+    fn rewrite_not(&self, e: &Expr) -> Option<(Expr, Rule)> {
+        match e {
+            Expr::Not(inner) if self.enabled(Rule::DoubleNegation) => {
+                Some(((**inner).clone(), Rule::DoubleNegation))
+            }
+            Expr::Const(b) if self.enabled(Rule::ConstantFolding) => {
+                Some((Expr::Const(!b), Rule::ConstantFolding))
+            }
+            _ if self.enabled(Rule::DeMorgan) => {
+                let (ty, operands) = junction_operands(e)?;
+                let negated = operands
+                    .into_iter()
+                    .map(|o| Expr::Not(Box::new(o.clone())))
+                    .collect();
+                let rewritten = if ty == BinaryOpType::And {
+                    Expr::disjunction(negated)
+                } else {
+                    Expr::conjunction(negated)
+                };
+                Some((rewritten, Rule::DeMorgan))
+            }
+            _ => None,
+        }
+    }
+
+    /// Handles the binary operators other than ∧ and ∨.
+    fn rewrite_binary(&self, bop: &BinaryOp) -> Option<(Expr, Rule)> {
+        use BinaryOpType::*;
+
+        let not = |e: &Expr| Expr::Not(Box::new(e.clone()));
+
+        if bop.ty == Implies && self.enabled(Rule::ImplicationElimination) {
+            let rewritten = Expr::disjunction(vec![not(&bop.l), bop.r.clone()]);
+            return Some((rewritten, Rule::ImplicationElimination));
+        }
+
+        if !self.enabled(Rule::ConstantFolding) {
+            return None;
+        }
+        // the operand that isn't a constant, and the constant
+        let (e, c, const_is_left) = match (&bop.l, &bop.r) {
+            (Expr::Const(c), e) => (e, *c, true),
+            (e, Expr::Const(c)) => (e, *c, false),
+            _ => return None,
+        };
+        let rewritten = match (bop.ty, c) {
+            (Implies, true) if const_is_left => e.clone(),
+            (Implies, false) if const_is_left => Expr::Const(true),
+            (Implies, true) => Expr::Const(true),
+            (Implies, false) => not(e),
+            (Equivalent, true) | (Xor, false) => e.clone(),
+            (Equivalent, false) | (Xor, true) => not(e),
+            (Nand, true) | (Nor, false) => not(e),
+            (Nand, false) => Expr::Const(true),
+            (Nor, true) => Expr::Const(false),
+            (And | Or, _) => unreachable!(),
+        };
+
+        Some((rewritten, Rule::ConstantFolding))
+    }
+
+    /// Handles binary and n-ary ∧ and ∨.
+    fn rewrite_junction(&self, expr: &Expr) -> Option<(Expr, Rule)> {
+        let (ty, operands) = junction_operands(expr)?;
+        // the identity element of the junction; its negation is the absorbing element
+        let identity = ty == BinaryOpType::And;
+        let rebuild = |operands: Vec<Expr>| {
+            if ty == BinaryOpType::And {
+                Expr::conjunction(operands)
+            } else {
+                Expr::disjunction(operands)
+            }
+        };
+
+        if self.enabled(Rule::ConstantFolding) {
+            if operands.contains(&&Expr::Const(!identity)) {
+                return Some((Expr::Const(!identity), Rule::ConstantFolding));
+            }
+            if operands.contains(&&Expr::Const(identity)) {
+                let rest = operands
+                    .iter()
+                    .filter(|o| ***o != Expr::Const(identity))
+                    .map(|o| (*o).clone())
+                    .collect();
+                return Some((rebuild(rest), Rule::ConstantFolding));
+            }
+        }
+
+        if self.enabled(Rule::Idempotence) {
+            let mut unique: Vec<Expr> = Vec::new();
+            for o in &operands {
+                if !unique.contains(o) {
+                    unique.push((*o).clone());
+                }
+            }
+            if unique.len() < operands.len() {
+                return Some((rebuild(unique), Rule::Idempotence));
+            }
+        }
+
+        if self.enabled(Rule::Complement)
+            && operands
+                .iter()
+                .any(|o| operands.contains(&&Expr::Not(Box::new((*o).clone()))))
+        {
+            return Some((Expr::Const(!identity), Rule::Complement));
+        }
+
+        if self.enabled(Rule::Absorption) {
+            // an operand that is the opposite junction of (among others) another operand is
+            // redundant
+            let absorbed = |o: &Expr| {
+                junction_operands(o).is_some_and(|(inner_ty, inner)| {
+                    inner_ty != ty && inner.iter().any(|i| operands.contains(i))
+                })
+            };
+            if operands.iter().any(|o| absorbed(o)) {
+                let rest = operands
+                    .iter()
+                    .filter(|o| !absorbed(o))
+                    .map(|o| (*o).clone())
+                    .collect();
+                return Some((rebuild(rest), Rule::Absorption));
+            }
+        }
+
+        None
+    }
+}
+
+/// Returns the operands of a (binary or n-ary) conjunction or disjunction.
+fn junction_operands(expr: &Expr) -> Option<(BinaryOpType, Vec<&Expr>)> {
+    let ty = expr.junction_type()?;
+    let operands = match expr {
+        Expr::BinaryOp(bop) => vec![&bop.l, &bop.r],
+        Expr::And(es) | Expr::Or(es) => es.iter().collect(),
+        _ => unreachable!(),
+    };
+
+    Some((ty, operands))
+}
+
+impl Expr {
+    /// Simplifies the expression using all the rules.
+    pub fn simplify(&self) -> Simplification {
+        Simplifier::default().simplify(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::SeedableRng;
+    use rand_xorshift::XorShiftRng;
+
+    use super::*;
+    use crate::{
+        analysis::TruthTable,
+        generator::{ExprGenerator, variable_names},
+        parser::Parser,
+    };
+
+    fn simplify(input: &str, rules: &[Rule]) -> (String, Vec<Rule>) {
+        let expr = Parser::new(input).parse().unwrap();
+        let Simplification { expr, rules } = Simplifier::new(rules).simplify(&expr);
+        (expr.to_string(), rules)
+    }
+
+    #[test]
+    fn rules() {
+        use Rule::*;
+
+        let reductions = &Rule::REDUCTIONS;
+        assert_eq!(
+            simplify("¬¬¬a", reductions),
+            ("¬a".into(), vec![DoubleNegation])
+        );
+        assert_eq!(
+            simplify("a ∧ b ∧ a", reductions),
+            ("a ∧ b".into(), vec![Idempotence])
+        );
+        assert_eq!(
+            simplify("a ∨ (b ∧ a)", reductions),
+            ("a".into(), vec![Absorption])
+        );
+        assert_eq!(
+            simplify("b ∨ a ∨ ¬b", reductions),
+            ("⊤".into(), vec![Complement])
+        );
+        assert_eq!(
+            simplify("(a → ⊥) ∧ ⊤", reductions),
+            ("¬a".into(), vec![ConstantFolding, ConstantFolding])
+        );
+        assert_eq!(simplify("a → b", reductions), ("a → b".into(), vec![]));
+
+        assert_eq!(
+            simplify("¬(a → b)", &Rule::ALL),
+            (
+                "a ∧ ¬b".into(),
+                vec![ImplicationElimination, DeMorgan, DoubleNegation]
+            )
+        );
+    }
+
+    #[test]
+    fn equivalence() {
+        let mut rng = XorShiftRng::seed_from_u64(3);
+        let generator = ExprGenerator::new(4, 3)
+            .with_const_probability(0.1)
+            .with_max_arity(3);
+        let range = variable_names(3);
+
+        for _ in 0..500 {
+            let expr = generator.generate(&range, &mut rng);
+            let simplified = expr.simplify().expr;
+            for (state, result) in TruthTable::new(&expr).rows() {
+                assert_eq!(
+                    simplified.evaluate(state.bindings()),
+                    result,
+                    "{expr} vs {simplified}"
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,194 @@
+use std::collections::HashMap;
+
+use mlml_util::SplitStrategy;
+use rusqlite::{Connection, OptionalExtension, Statement};
+
+use crate::{
+    analysis::TruthTable,
+    expr::{Expr, ExprStructure},
+};
+
+pub const TRAIN: usize = 0;
+pub const VALID: usize = 1;
+pub const TEST: usize = 2;
+
+/// Decides which of the train, valid and test splits a generated expression may go to.
+pub struct SplitFilter {
+    strategy: SplitStrategy,
+    /// The group of splits each structure was first accepted into; only used by the structure
+    /// strategy.
+    structure_groups: HashMap<ExprStructure, usize>,
+}
+
+impl SplitFilter {
+    pub fn new(strategy: SplitStrategy) -> Self {
+        Self {
+            strategy,
+            structure_groups: HashMap::new(),
+        }
+    }
+
+    /// The max depth to generate the expressions of the given split with, if the strategy
+    /// determines it.
+    pub fn max_depth(&self, split: usize) -> Option<usize> {
+        match self.strategy {
+            SplitStrategy::Depth { max_train_depth } if split == TEST => Some(max_train_depth + 1),
+            SplitStrategy::Depth { max_train_depth } => Some(max_train_depth),
+            _ => None,
+        }
+    }
+
+    /// Whether the expression may be added to the given split.
+    pub fn accepts(&self, split: usize, expr: &Expr) -> bool {
+        match self.strategy {
+            SplitStrategy::Random => true,
+            SplitStrategy::Structure { .. } => self
+                .structure_groups
+                .get(&expr.to_structure())
+                .is_none_or(|group| *group == self.group(split)),
+            SplitStrategy::Depth { max_train_depth } => {
+                if split == TEST {
+                    expr.height() == max_train_depth + 1
+                } else {
+                    expr.height() <= max_train_depth
+                }
+            }
+        }
+    }
+
+    /// Records that the expression was added to the given split.
+    pub fn insert(&mut self, split: usize, expr: &Expr) {
+        if let SplitStrategy::Structure { .. } = self.strategy {
+            let group = self.group(split);
+            self.structure_groups
+                .entry(expr.to_structure())
+                .or_insert(group);
+        }
+    }
+
+    /// The splits whose structures may overlap share a group.
+    fn group(&self, split: usize) -> usize {
+        match self.strategy {
+            SplitStrategy::Structure {
+                disjoint_valid: false,
+            } if split == VALID => TRAIN,
+            _ => split,
+        }
+    }
+}
+
+/// Keeps expressions that are equivalent up to a renaming of their variables in a single split.
+///
+/// The split each canonical truth table was first accepted into is kept in a temporary table of
+/// the database rather than in memory, keyed by [`TruthTable::to_bytes`].
+pub struct EquivalenceFilter<'a> {
+    select_split: Statement<'a>,
+    insert_table: Statement<'a>,
+}
+
+impl<'a> EquivalenceFilter<'a> {
+    pub fn new(connection: &'a Connection) -> rusqlite::Result<Self> {
+        connection.execute(
+            "CREATE TEMP TABLE canonical_table_splits (truth_table BLOB PRIMARY KEY, split INTEGER)",
+            (),
+        )?;
+
+        Ok(Self {
+            select_split: connection
+                .prepare("SELECT split FROM canonical_table_splits WHERE truth_table = ?1")?,
+            insert_table: connection.prepare(
+                "INSERT OR IGNORE INTO canonical_table_splits (truth_table, split) VALUES (?1, ?2)",
+            )?,
+        })
+    }
+
+    /// The table identifying the expression's equivalence class, if it is checked: tautologies and
+    /// contradictions are all equivalent to each other, and every split needs them, while
+    /// expressions with too many variables for a truth table aren't checked.
+    pub fn canonical_table(expr: &Expr) -> Option<TruthTable> {
+        TruthTable::canonical_up_to_renaming(expr).filter(|table| !table.variables().is_empty())
+    }
+
+    /// Whether an expression with the given canonical table may be added to the given split.
+    pub fn accepts(&mut self, split: usize, table: &TruthTable) -> rusqlite::Result<bool> {
+        let table_split: Option<usize> = self
+            .select_split
+            .query_row((table.to_bytes(),), |row| row.get(0))
+            .optional()?;
+
+        Ok(table_split.is_none_or(|table_split| table_split == split))
+    }
+
+    /// Records that an expression with the given canonical table was added to the given split.
+    pub fn insert(&mut self, split: usize, table: &TruthTable) -> rusqlite::Result<()> {
+        self.insert_table.execute((table.to_bytes(), split))?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::Parser;
+
+    fn parse(input: &str) -> Expr {
+        Parser::new(input).parse().unwrap()
+    }
+
+    #[test]
+    fn structure() {
+        let mut filter = SplitFilter::new(SplitStrategy::Structure {
+            disjoint_valid: false,
+        });
+        filter.insert(TRAIN, &parse("a ∧ b"));
+        filter.insert(TEST, &parse("¬a"));
+
+        assert!(filter.accepts(VALID, &parse("c ∧ a")));
+        assert!(!filter.accepts(TEST, &parse("c ∧ a")));
+        assert!(filter.accepts(TEST, &parse("¬b")));
+        assert!(!filter.accepts(TRAIN, &parse("¬b")));
+        assert!(filter.accepts(TEST, &parse("a ∨ b")));
+
+        let mut filter = SplitFilter::new(SplitStrategy::Structure {
+            disjoint_valid: true,
+        });
+        filter.insert(TRAIN, &parse("a ∧ b"));
+        assert!(!filter.accepts(VALID, &parse("c ∧ a")));
+    }
+
+    #[test]
+    fn depth() {
+        let filter = SplitFilter::new(SplitStrategy::Depth { max_train_depth: 1 });
+        assert_eq!(filter.max_depth(TRAIN), Some(1));
+        assert_eq!(filter.max_depth(TEST), Some(2));
+
+        assert!(filter.accepts(TRAIN, &parse("a")));
+        assert!(filter.accepts(VALID, &parse("a → b")));
+        assert!(!filter.accepts(TRAIN, &parse("a → ¬b")));
+        assert!(filter.accepts(TEST, &parse("(a ∧ b) ∨ c ∨ d")));
+        assert!(!filter.accepts(TEST, &parse("a → b")));
+    }
+
+    #[test]
+    fn equivalence() {
+        let table = |input: &str| EquivalenceFilter::canonical_table(&parse(input));
+
+        let connection = Connection::open_in_memory().unwrap();
+        let mut filter = EquivalenceFilter::new(&connection).unwrap();
+        filter.insert(TRAIN, &table("a ∧ b").unwrap()).unwrap();
+        filter.insert(TEST, &table("x → y").unwrap()).unwrap();
+        // the split an equivalence class was first accepted into is kept
+        filter.insert(VALID, &table("b ∧ a").unwrap()).unwrap();
+
+        let mut accepts = |split, input| filter.accepts(split, &table(input).unwrap()).unwrap();
+        assert!(!accepts(TEST, "c ∧ d"));
+        assert!(!accepts(VALID, "¬(¬d ∨ ¬c)"));
+        assert!(accepts(TRAIN, "c ∧ d"));
+        assert!(!accepts(TRAIN, "¬b ∨ a"));
+        assert!(accepts(TEST, "c ∨ d"));
+
+        assert_eq!(table("a ∨ ¬a"), None);
+        assert_eq!(table("b ∧ (a ∧ ¬a)"), None);
+    }
+}
@@ -36,20 +36,10 @@ fn main() {
     let config_str = std::fs::read_to_string(config_path()).unwrap();
     let config: MlmlConfig = serde_json::from_str(&config_str).unwrap();
 
-    let connection = rusqlite::Connection::open(&config.dataset.db_path).unwrap();
-    let query = "SELECT * FROM test";
-    let mut stmt = connection.prepare(query).unwrap();
-    let mut rows = stmt.query([]).unwrap();
-
-    let mut test_samples = Vec::new();
-    while let Some(row) = rows.next().unwrap() {
-        test_samples.push((
-            row.get(0).unwrap(),
-            row.get(1).unwrap(),
-            row.get(2).unwrap(),
-            row.get(3).unwrap(),
-        ));
-    }
+    let test_samples: Vec<_> = RawDataset::test(&config.dataset)
+        .raw_items()
+        .map(|item| (item.expression, item.result, item.complexity, item.rarity))
+        .collect();
 
     #[cfg(feature = "tch-cpu")]
     tch_cpu::run(test_samples, config);
@@ -31,8 +31,8 @@ pub fn launch<B: AutodiffBackend>(devices: Vec<B::Device>, mlml_config: MlmlConf
 
     mlml_model::training::train::<B, RawDataset>(
         devices,
-        RawDataset::train(&mlml_config.dataset.db_path),
-        RawDataset::validate(&mlml_config.dataset.db_path),
+        RawDataset::train(&mlml_config.dataset),
+        RawDataset::validate(&mlml_config.dataset),
         config,
         "/tmp/mlml_model",
         mlml_config,
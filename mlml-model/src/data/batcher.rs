@@ -6,14 +6,29 @@
 use std::sync::Arc;
 
 use burn::{data::dataloader::batcher::Batcher, nn::attention::generate_padding_mask, prelude::*};
+use mlml_util::Architecture;
 
 use super::{dataset::MlmlItem, tokenizer::Tokenizer};
 
+/// The depth up to which the tree coordinates of the tokens are distinguished; deeper nodes share
+/// the coordinates of their ancestor at that depth.
+pub const MAX_TREE_DEPTH: usize = 16;
+/// The number of children of a node that are distinguished in the tree coordinates; further
+/// children share the last one's.
+pub const MAX_TREE_BRANCHING: usize = 4;
+/// The kinds of edges of the expressions' DAGs: from the first child to its parent, from any other
+/// child to its parent, and the reverses of both.
+pub const EDGE_RELATIONS: usize = 4;
+
 /// Struct for batching text classification items
 #[derive(Clone, new)]
 pub struct MlmlBatcher {
     tokenizer: Arc<dyn Tokenizer>, // Tokenizer for converting text to token IDs
     max_seq_length: usize,         // Maximum sequence length for tokenized text
+    #[new(default)]
+    tree_positions: bool, // Whether to compute the tree coordinates of the tokens
+    #[new(default)]
+    architecture: Architecture, // The model the batches are for, which determines their contents
 }
 
 #[derive(Debug, Clone, new)]
@@ -21,12 +36,197 @@ pub struct TrainingBatch<B: Backend> {
     pub tokens: Tensor<B, 2, Int>,    // Tokenized text
     pub labels: Tensor<B, 1, Int>,    // Labels of the text
     pub mask_pad: Tensor<B, 2, Bool>, // Padding mask for the tokenized text
+    pub tree: Option<TreeBatch<B>>,   // Parse tree coordinates of the tokens, if computed
+    pub expr: Option<ExprBatch<B>>,   // Parse trees of the expressions, if computed
+    pub graph: Option<GraphBatch<B>>, // DAGs of the expressions, if computed
 }
 
 #[derive(Debug, Clone, new)]
 pub struct InferenceBatch<B: Backend> {
     pub tokens: Tensor<B, 2, Int>,    // Tokenized text
     pub mask_pad: Tensor<B, 2, Bool>, // Padding mask for the tokenized text
+    pub tree: Option<TreeBatch<B>>,   // Parse tree coordinates of the tokens, if computed
+    pub expr: Option<ExprBatch<B>>,   // Parse trees of the expressions, if computed
+    pub graph: Option<GraphBatch<B>>, // DAGs of the expressions, if computed
+}
+
+/// The coordinates of the tokens in the parse trees of their expressions
+#[derive(Debug, Clone, new)]
+pub struct TreeBatch<B: Backend> {
+    pub depths: Tensor<B, 2, Int>, // Depth of each token's node, 1 at the root and 0 outside
+    pub paths: Tensor<B, 3, Int>, // Index + 1 of each node on the path from the root, 0 past the end
+    pub parents: Tensor<B, 2, Int>, // Index of the token of each node's parent, 0 if there is none
+}
+
+/// The parse trees of the expressions; the nodes of all of them are ordered by height, so that the
+/// nodes of each height can be processed at once. The nodes are numbered from 1, with 0 standing for
+/// a missing child.
+#[derive(Debug, Clone, new)]
+pub struct ExprBatch<B: Backend> {
+    pub tokens: Tensor<B, 1, Int>,   // Token of each node's symbol
+    pub values: Tensor<B, 1, Int>, // Value of each variable: 0 if unassigned, 1 if false, 2 if true
+    pub children: Tensor<B, 2, Int>, // Numbers of each node's two children
+    pub level_sizes: Vec<usize>,   // Number of nodes of each height
+    pub roots: Tensor<B, 1, Int>,  // Number of the root of each expression
+}
+
+/// The DAGs of the expressions, as the components of a single graph whose nodes are numbered from
+/// 0. Every edge between a node and its child is included in both directions.
+#[derive(Debug, Clone, new)]
+pub struct GraphBatch<B: Backend> {
+    pub tokens: Tensor<B, 1, Int>,    // Token of each node's symbol
+    pub values: Tensor<B, 1, Int>, // Value of each variable: 0 if unassigned, 1 if false, 2 if true
+    pub sources: Tensor<B, 1, Int>, // Source node of each edge
+    pub targets: Tensor<B, 1, Int>, // Target node of each edge
+    pub relations: Tensor<B, 1, Int>, // Kind of each edge, below `EDGE_RELATIONS`
+    pub graphs: Tensor<B, 1, Int>, // Expression each node belongs to
+    pub roots: Tensor<B, 1, Int>,  // Root node of each expression
+}
+
+impl MlmlBatcher {
+    /// Sets whether the batches include the tree coordinates of the tokens, which requires parsing
+    /// the texts; only the transformer reads them
+    pub fn with_tree_positions(mut self, tree_positions: bool) -> Self {
+        self.tree_positions = tree_positions;
+        self
+    }
+
+    /// Sets the architecture of the model the batches are for; only the inputs it reads are
+    /// computed
+    pub fn with_architecture(mut self, architecture: Architecture) -> Self {
+        self.architecture = architecture;
+        self
+    }
+
+    /// Computes the tree coordinates of the tokenized texts
+    fn tree_batch<B: Backend>(&self, texts: &[&str], device: &B::Device) -> TreeBatch<B> {
+        let mut depths = Vec::with_capacity(texts.len() * self.max_seq_length);
+        let mut paths = Vec::with_capacity(depths.capacity() * MAX_TREE_DEPTH);
+        let mut parents = Vec::with_capacity(depths.capacity());
+
+        for text in texts {
+            for coordinates in self.tokenizer.tree_coordinates(text) {
+                depths.push(coordinates.depth.min(MAX_TREE_DEPTH) as i64);
+                let mut path = coordinates
+                    .path
+                    .iter()
+                    .take(MAX_TREE_DEPTH)
+                    .map(|i| (i + 1).min(MAX_TREE_BRANCHING) as i64)
+                    .collect::<Vec<_>>();
+                path.resize(MAX_TREE_DEPTH, 0);
+                paths.extend(path);
+                parents.push(coordinates.parent as i64);
+            }
+        }
+
+        let shape = [texts.len(), self.max_seq_length];
+        TreeBatch {
+            depths: Tensor::from_data(TensorData::new(depths, shape), device),
+            paths: Tensor::from_data(
+                TensorData::new(paths, [shape[0], shape[1], MAX_TREE_DEPTH]),
+                device,
+            ),
+            parents: Tensor::from_data(TensorData::new(parents, shape), device),
+        }
+    }
+
+    /// Collects the parse trees of the texts
+    fn expr_batch<B: Backend>(&self, texts: &[&str], device: &B::Device) -> ExprBatch<B> {
+        let trees = texts
+            .iter()
+            .map(|text| self.tokenizer.tree_nodes(text))
+            .collect::<Vec<_>>();
+
+        // the (height, tree, node) of every node, in the order of their numbers
+        let mut order = Vec::new();
+        for (t, nodes) in trees.iter().enumerate() {
+            let mut heights = Vec::with_capacity(nodes.len());
+            for (n, node) in nodes.iter().enumerate() {
+                assert!(node.children.len() <= 2);
+                let height = node.children.iter().map(|c| heights[*c] + 1).max();
+                heights.push(height.unwrap_or(0));
+                order.push((heights[n], t, n));
+            }
+        }
+        order.sort_unstable();
+
+        let mut numbers = trees
+            .iter()
+            .map(|nodes| vec![0; nodes.len()])
+            .collect::<Vec<_>>();
+        for (i, (_, t, n)) in order.iter().enumerate() {
+            numbers[*t][*n] = i as i64 + 1;
+        }
+
+        let mut tokens = Vec::with_capacity(order.len());
+        let mut values = Vec::with_capacity(order.len());
+        let mut children = Vec::with_capacity(order.len() * 2);
+        let mut level_sizes = Vec::new();
+        for (height, t, n) in order {
+            let node = &trees[t][n];
+            tokens.push(node.token as i64);
+            values.push(node.value.map_or(0, |value| value as i64 + 1));
+            children.extend((0..2).map(|i| node.children.get(i).map_or(0, |c| numbers[t][*c])));
+            if level_sizes.len() == height {
+                level_sizes.push(0);
+            }
+            level_sizes[height] += 1;
+        }
+        let roots = numbers
+            .iter()
+            .map(|numbers| *numbers.last().unwrap())
+            .collect::<Vec<_>>();
+
+        let n_nodes = tokens.len();
+        ExprBatch {
+            tokens: Tensor::from_data(TensorData::new(tokens, [n_nodes]), device),
+            values: Tensor::from_data(TensorData::new(values, [n_nodes]), device),
+            children: Tensor::from_data(TensorData::new(children, [n_nodes, 2]), device),
+            level_sizes,
+            roots: Tensor::from_data(TensorData::new(roots, [texts.len()]), device),
+        }
+    }
+
+    /// Joins the DAGs of the texts
+    fn graph_batch<B: Backend>(&self, texts: &[&str], device: &B::Device) -> GraphBatch<B> {
+        let mut tokens = Vec::new();
+        let mut values = Vec::new();
+        let mut sources = Vec::new();
+        let mut targets = Vec::new();
+        let mut relations = Vec::new();
+        let mut graphs = Vec::new();
+        let mut roots = Vec::with_capacity(texts.len());
+
+        for (g, text) in texts.iter().enumerate() {
+            let offset = tokens.len() as i64;
+            for (n, node) in self.tokenizer.dag_nodes(text).into_iter().enumerate() {
+                let n = offset + n as i64;
+                tokens.push(node.token as i64);
+                values.push(node.value.map_or(0, |value| value as i64 + 1));
+                graphs.push(g as i64);
+                for (i, child) in node.children.into_iter().enumerate() {
+                    let child = offset + child as i64;
+                    let relation = i.min(1) as i64;
+                    sources.extend([child, n]);
+                    targets.extend([n, child]);
+                    relations.extend([relation, relation + 2]);
+                }
+            }
+            roots.push(tokens.len() as i64 - 1);
+        }
+
+        let n_nodes = tokens.len();
+        let n_edges = sources.len();
+        GraphBatch {
+            tokens: Tensor::from_data(TensorData::new(tokens, [n_nodes]), device),
+            values: Tensor::from_data(TensorData::new(values, [n_nodes]), device),
+            sources: Tensor::from_data(TensorData::new(sources, [n_edges]), device),
+            targets: Tensor::from_data(TensorData::new(targets, [n_edges]), device),
+            relations: Tensor::from_data(TensorData::new(relations, [n_edges]), device),
+            graphs: Tensor::from_data(TensorData::new(graphs, [n_nodes]), device),
+            roots: Tensor::from_data(TensorData::new(roots, [texts.len()]), device),
+        }
+    }
 }
 
 /// Implement Batcher trait for Batcher struct for training
@@ -37,7 +237,7 @@ impl<B: Backend> Batcher<B, MlmlItem, TrainingBatch<B>> for MlmlBatcher {
         let mut labels_list = Vec::with_capacity(items.len());
 
         // Tokenize text and create label tensor for each item
-        for item in items {
+        for item in &items {
             tokens_list.push(self.tokenizer.encode(&item.text));
             labels_list.push(Tensor::from_data(
                 TensorData::from([(item.label as i64).elem::<B::IntElem>()]),
@@ -53,11 +253,22 @@ impl<B: Backend> Batcher<B, MlmlItem, TrainingBatch<B>> for MlmlBatcher {
             device,
         );
 
+        let texts = items
+            .iter()
+            .map(|item| item.text.as_str())
+            .collect::<Vec<_>>();
+
         // Create and return training batch
         TrainingBatch {
             tokens: mask.tensor,
             labels: Tensor::cat(labels_list, 0),
             mask_pad: mask.mask,
+            tree: (self.architecture == Architecture::Transformer && self.tree_positions)
+                .then(|| self.tree_batch(&texts, device)),
+            expr: (self.architecture == Architecture::TreeLstm)
+                .then(|| self.expr_batch(&texts, device)),
+            graph: (self.architecture == Architecture::Gnn)
+                .then(|| self.graph_batch(&texts, device)),
         }
     }
 }
@@ -69,8 +280,8 @@ impl<B: Backend> Batcher<B, String, InferenceBatch<B>> for MlmlBatcher {
         let mut tokens_list = Vec::with_capacity(items.len());
 
         // Tokenize each string
-        for item in items {
-            tokens_list.push(self.tokenizer.encode(&item));
+        for item in &items {
+            tokens_list.push(self.tokenizer.encode(item));
         }
 
         // Generate padding mask for tokenized text
@@ -81,10 +292,54 @@ impl<B: Backend> Batcher<B, String, InferenceBatch<B>> for MlmlBatcher {
             device,
         );
 
+        let texts = items.iter().map(String::as_str).collect::<Vec<_>>();
+
         // Create and return inference batch
         InferenceBatch {
             tokens: mask.tensor.to_device(device),
             mask_pad: mask.mask.to_device(device),
+            tree: (self.architecture == Architecture::Transformer && self.tree_positions)
+                .then(|| self.tree_batch(&texts, device)),
+            expr: (self.architecture == Architecture::TreeLstm)
+                .then(|| self.expr_batch(&texts, device)),
+            graph: (self.architecture == Architecture::Gnn)
+                .then(|| self.graph_batch(&texts, device)),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use burn::backend::NdArray;
+
+    use super::*;
+    use crate::data::MlmlTokenizer;
+
+    type B = NdArray;
+
+    #[test]
+    fn optional_inputs() {
+        let tokenizer = Arc::new(MlmlTokenizer::new(32, 3));
+        let batch = |batcher: MlmlBatcher| -> InferenceBatch<B> {
+            batcher.batch(vec!["[a: true] a ∧ ¬b".into()], &Default::default())
+        };
+
+        // only the inputs the configured model reads are computed
+        let transformer = batch(MlmlBatcher::new(tokenizer.clone(), 32));
+        assert!(transformer.tree.is_none() && transformer.expr.is_none());
+        assert!(transformer.graph.is_none());
+        let tree_positions =
+            batch(MlmlBatcher::new(tokenizer.clone(), 32).with_tree_positions(true));
+        assert!(tree_positions.tree.is_some() && tree_positions.expr.is_none());
+
+        let tree_lstm = batch(
+            MlmlBatcher::new(tokenizer.clone(), 32)
+                .with_tree_positions(true)
+                .with_architecture(Architecture::TreeLstm),
+        );
+        assert!(tree_lstm.tree.is_none() && tree_lstm.expr.is_some());
+        assert!(tree_lstm.graph.is_none());
+        let gnn = batch(MlmlBatcher::new(tokenizer, 32).with_architecture(Architecture::Gnn));
+        assert!(gnn.expr.is_none() && gnn.graph.is_some());
+    }
+}
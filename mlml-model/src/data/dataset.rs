@@ -6,6 +6,7 @@
 use std::path::Path;
 
 use burn::data::dataset::{Dataset, SqliteDataset, SqliteDatasetStorage};
+use mlml_util::{DatasetConfig, DatasetMetadata};
 
 // Define a struct for text classification items
 #[derive(new, Clone, Debug)]
@@ -23,17 +24,25 @@ pub trait MlmlDataset: Dataset<MlmlItem> {
 pub struct RawItem {
     pub expression: String, // The text for classification
     pub result: String,     // The label of the text (classification category)
+    pub complexity: usize,  // The complexity of the expression
+    pub rarity: f32,        // The share of the samples with the expression's structure
 }
 
 pub struct RawDataset {
     dataset: SqliteDataset<RawItem>,
+    classes: &'static [&'static str],
 }
 
 impl Dataset<MlmlItem> for RawDataset {
     fn get(&self, index: usize) -> Option<MlmlItem> {
-        self.dataset
-            .get(index)
-            .map(|item| MlmlItem::new(item.expression, (item.result == "true") as usize))
+        self.dataset.get(index).map(|item| {
+            let label = self
+                .classes
+                .iter()
+                .position(|c| *c == item.result)
+                .unwrap_or_else(|| panic!("unknown class: '{}'", item.result));
+            MlmlItem::new(item.expression, label)
+        })
     }
 
     fn len(&self) -> usize {
@@ -42,19 +51,60 @@ impl Dataset<MlmlItem> for RawDataset {
 }
 
 impl RawDataset {
-    pub fn new(db_path: &Path, split: &str) -> Self {
-        let dataset: SqliteDataset<RawItem> = SqliteDatasetStorage::from_file(db_path)
+    /// Loads a split of the dataset at `config.db_path`, after checking that it is compatible with
+    /// the config.
+    pub fn new(config: &DatasetConfig, split: &str) -> Self {
+        check_dataset(config);
+
+        let dataset: SqliteDataset<RawItem> = SqliteDatasetStorage::from_file(&config.db_path)
             .reader(split)
             .unwrap();
-        Self { dataset }
+        Self {
+            dataset,
+            classes: config.task.classes(),
+        }
+    }
+
+    pub fn train(config: &DatasetConfig) -> Self {
+        Self::new(config, "train")
     }
 
-    pub fn train(db_path: &Path) -> Self {
-        Self::new(db_path, "train")
+    pub fn validate(config: &DatasetConfig) -> Self {
+        Self::new(config, "valid")
     }
 
-    pub fn validate(db_path: &Path) -> Self {
-        Self::new(db_path, "valid")
+    pub fn test(config: &DatasetConfig) -> Self {
+        Self::new(config, "test")
+    }
+
+    /// Iterates over the rows of the split as they are stored.
+    pub fn raw_items(&self) -> impl Iterator<Item = RawItem> + '_ {
+        self.dataset.iter()
+    }
+}
+
+/// Reads the metadata of the dataset at the given path.
+pub fn load_metadata(db_path: &Path) -> DatasetMetadata {
+    let connection = rusqlite::Connection::open(db_path).unwrap();
+    let mut stmt = connection
+        .prepare("SELECT key, value FROM metadata")
+        .unwrap_or_else(|e| {
+            panic!("the dataset has no metadata ({e}); regenerate it with mlml-dataset")
+        });
+    let rows = stmt
+        .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+        .unwrap()
+        .map(Result::unwrap);
+
+    DatasetMetadata::from_rows(rows).unwrap_or_else(|e| {
+        panic!("the dataset metadata is invalid ({e}); regenerate it with mlml-dataset")
+    })
+}
+
+/// Panics with an explanation if the dataset at `config.db_path` can't be used with the config.
+pub fn check_dataset(config: &DatasetConfig) {
+    if let Err(e) = load_metadata(&config.db_path).check_compatibility(config) {
+        panic!("incompatible dataset at {}: {e}", config.db_path.display());
     }
 }
 
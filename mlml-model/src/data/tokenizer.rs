@@ -4,6 +4,10 @@
 
 use std::collections::HashMap;
 
+use mlml_dataset::{
+    expr::{BinaryOpType, Expr, Notation},
+    parser::Parser,
+};
 use smol_str::{SmolStr, ToSmolStr};
 
 #[derive(Debug)]
@@ -14,10 +18,53 @@ pub struct MlmlTokenizer {
 }
 
 const VALUES: &[&str] = &["true", "false"];
-const OPERATORS: &[&str] = &["∧", "∨", "¬", "→", "↔"];
+// constants within the expression; kept apart from the state values above
+const CONSTANTS: &[&str] = &["⊤", "⊥"];
+const OPERATORS: &[&str] = &["∧", "∨", "¬", "→", "↔", "⊕", "↑", "↓"];
+// ASCII spellings of the operators, longest first, mapped to the same tokens as the Unicode ones
+const OPERATOR_ALIASES: &[(&str, &str)] = &[
+    ("<->", "↔"),
+    ("<=>", "↔"),
+    ("&&", "∧"),
+    ("!&", "↑"),
+    ("!|", "↓"),
+    ("||", "∨"),
+    ("->", "→"),
+    ("=>", "→"),
+    ("&", "∧"),
+    ("|", "∨"),
+    ("^", "⊕"),
+    ("!", "¬"),
+    ("~", "¬"),
+];
 const MISC: &[&str] = &["[", "]", ":", ",", "(", ")"];
+/// The position of a token in the parse tree of the expression.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TreeCoordinates {
+    /// The depth of the token's node, counting from 1 at the root; 0 outside the expression.
+    pub depth: usize,
+    /// The index of each node on the way from the root to the token's node among the children of
+    /// its parent.
+    pub path: Vec<usize>,
+    /// The index of the token of the node's parent, or of the `<cls>` token if there is none.
+    pub parent: usize,
+}
+
+/// A node of the parse tree of an expression, in which n-ary conjunctions and disjunctions are
+/// nested binary ones.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TreeNode {
+    /// The token of the node's symbol.
+    pub token: usize,
+    /// The value of a variable, if the state assigns one.
+    pub value: Option<bool>,
+    /// The indices of the node's children, which precede it.
+    pub children: Vec<usize>,
+}
+
 const STRUCT: &[&str] = &[
     "<pad>",
+    "<cls>",
     "<assign>",
     "</assign>",
     "<operator_prefix>",
@@ -38,6 +85,20 @@ pub trait Tokenizer: Send + Sync {
     /// Gets the token used for padding sequences to a consistent length.
     fn pad_token(&self) -> usize;
 
+    /// Gets the token that starts every sequence, whose output the classifier may read.
+    fn cls_token(&self) -> usize;
+
+    /// Gets the position in the expression's parse tree of each of the tokens `encode` returns.
+    fn tree_coordinates(&self, value: &str) -> Vec<TreeCoordinates>;
+
+    /// Gets the nodes of the expression's parse tree, children first; the root is the last one.
+    fn tree_nodes(&self, value: &str) -> Vec<TreeNode>;
+
+    /// Gets the nodes of the expression's DAG, in which every variable is a single node shared by
+    /// all its occurrences and conjunctions and disjunctions keep all their operands as children;
+    /// children come first and the root is the last one.
+    fn dag_nodes(&self, value: &str) -> Vec<TreeNode>;
+
     /// Gets the string representation of the padding token.
     /// The default implementation uses `decode` on the padding token.
     fn pad_token_value(&self) -> String {
@@ -51,6 +112,7 @@ impl MlmlTokenizer {
         tokens.extend(STRUCT.iter().map(|s| SmolStr::new_inline(s)));
         tokens.extend(MISC.iter().map(|s| SmolStr::new_inline(s)));
         tokens.extend(VALUES.iter().map(|s| SmolStr::new_inline(s)));
+        tokens.extend(CONSTANTS.iter().map(|s| SmolStr::new_inline(s)));
         tokens.extend(OPERATORS.iter().map(|s| SmolStr::new_inline(s)));
         let vars = (0..max_vars).map(|n| SmolStr::from(format!("<var{n}>")));
         tokens.extend(vars);
@@ -78,29 +140,65 @@ impl Tokenizer for MlmlTokenizer {
         let mut tokens = Vec::new();
         let mut i = 0;
         let chars: Vec<char> = input.chars().collect();
-        let mut vars: HashMap<char, usize> = Default::default();
+        let mut vars: HashMap<String, usize> = Default::default();
 
         let mut in_state = false;
         let mut in_expr = false;
         let mut in_assignment = false;
 
+        tokens.push(self.cls_token());
+
+        // State-free inputs consist of just the expression
+        if !input.trim_start().starts_with('[') {
+            tokens.push(self.vocab["("]);
+            in_expr = true;
+        }
+
         while i < chars.len() {
-            if i + 3 < chars.len() && chars[i..i + 4] == ['t', 'r', 'u', 'e'] {
-                tokens.push(self.vocab["<value_prefix>"]);
-                tokens.push(self.vocab["true"]);
-                if in_assignment {
-                    tokens.push(self.vocab["</assign>"]);
-                    in_assignment = false;
+            if chars[i].is_alphabetic() {
+                // Identifiers: a letter followed by letters, digits or underscores
+                let len = chars[i..]
+                    .iter()
+                    .take_while(|c| c.is_alphanumeric() || **c == '_')
+                    .count();
+                let word: String = chars[i..i + len].iter().collect();
+                i += len;
+
+                if VALUES.contains(&word.as_str()) {
+                    if in_expr {
+                        let constant = if word == "true" { "⊤" } else { "⊥" };
+                        tokens.push(self.vocab[constant]);
+                        continue;
+                    }
+                    tokens.push(self.vocab["<value_prefix>"]);
+                    tokens.push(self.vocab[word.as_str()]);
+                    if in_assignment {
+                        tokens.push(self.vocab["</assign>"]);
+                        in_assignment = false;
+                    }
+                    continue;
                 }
-                i += 4;
-            } else if i + 4 < chars.len() && chars[i..i + 5] == ['f', 'a', 'l', 's', 'e'] {
-                tokens.push(self.vocab["<value_prefix>"]);
-                tokens.push(self.vocab["false"]);
-                if in_assignment {
-                    tokens.push(self.vocab["</assign>"]);
-                    in_assignment = false;
+
+                if in_state && !in_assignment {
+                    in_assignment = true;
+                    tokens.push(self.vocab["<assign>"]);
                 }
-                i += 5;
+                let pos = vars.len();
+                let var =
+                    SmolStr::from(format!("<var{}>", vars.entry(word.clone()).or_insert(pos)));
+                tokens.push(
+                    self.vocab
+                        .get(&var)
+                        .copied()
+                        .unwrap_or_else(|| panic!("missing token: '{word}'")),
+                );
+            } else if let Some((alias, op)) = OPERATOR_ALIASES
+                .iter()
+                .find(|(alias, _)| starts_with(&chars[i..], alias))
+            {
+                tokens.push(self.vocab["<operator_prefix>"]);
+                tokens.push(self.vocab[*op]);
+                i += alias.chars().count();
             } else if OPERATORS.contains(&&*chars[i].to_smolstr()) {
                 tokens.push(self.vocab["<operator_prefix>"]);
                 tokens.push(self.vocab[&chars[i].to_smolstr()]);
@@ -117,23 +215,6 @@ impl Tokenizer for MlmlTokenizer {
                 tokens.push(self.vocab["("]);
                 in_expr = true;
                 i += 1;
-            } else if chars[i].is_alphabetic() {
-                if in_state && !in_assignment {
-                    in_assignment = true;
-                    tokens.push(self.vocab["<assign>"]);
-                }
-                if !vars.contains_key(&chars[i]) {
-                    let pos = vars.len();
-                    vars.insert(chars[i], pos);
-                }
-                let var = SmolStr::from(format!("<var{}>", vars.get(&chars[i]).unwrap()));
-                tokens.push(
-                    self.vocab
-                        .get(&var)
-                        .copied()
-                        .unwrap_or_else(|| panic!("missing token: '{}'", chars[i])),
-                );
-                i += 1;
             } else {
                 // Remaining single-char tokens
                 let c = chars[i].to_smolstr();
@@ -151,7 +232,12 @@ impl Tokenizer for MlmlTokenizer {
             tokens.push(self.vocab[")"]);
         }
 
-        assert!(tokens.len() <= self.max_seq_length);
+        assert!(
+            tokens.len() <= self.max_seq_length,
+            "'{input}' is encoded into {} tokens, over the max_seq_length of {}",
+            tokens.len(),
+            self.max_seq_length
+        );
         // Pad to max_seq_length
         while tokens.len() < self.max_seq_length {
             tokens.push(self.vocab["<pad>"]);
@@ -181,10 +267,248 @@ impl Tokenizer for MlmlTokenizer {
     fn pad_token(&self) -> usize {
         0
     }
+
+    fn cls_token(&self) -> usize {
+        self.vocab["<cls>"]
+    }
+
+    fn tree_coordinates(&self, input: &str) -> Vec<TreeCoordinates> {
+        let tokens = self.encode(input);
+        let positions = parse(input).1.tree_positions();
+
+        // the expression starts with the first parenthesis, the one wrapping it; its tokens are
+        // listed in the same order as the positions of its symbols
+        let start = tokens.iter().position(|t| *t == self.vocab["("]).unwrap();
+        let symbol_tokens = (start..tokens.len())
+            .filter(|i| self.is_symbol(tokens[*i]))
+            .collect::<Vec<_>>();
+        assert_eq!(symbol_tokens.len(), positions.len());
+
+        let mut coordinates = vec![TreeCoordinates::default(); tokens.len()];
+        for (i, position) in symbol_tokens.iter().zip(positions) {
+            coordinates[*i] = TreeCoordinates {
+                depth: position.depth + 1,
+                path: position.path,
+                parent: position.parent.map_or(0, |parent| symbol_tokens[parent]),
+            };
+            if tokens[i - 1] == self.vocab["<operator_prefix>"] {
+                coordinates[i - 1] = coordinates[*i].clone();
+            }
+        }
+
+        // a pair of parentheses belongs to the node they wrap, the shallowest one between them
+        let mut open_parens = Vec::new();
+        for i in start..tokens.len() {
+            if tokens[i] == self.vocab["("] {
+                open_parens.push(i);
+            } else if tokens[i] == self.vocab[")"] {
+                let open = open_parens.pop().unwrap();
+                let node = symbol_tokens
+                    .iter()
+                    .filter(|j| (open..i).contains(*j))
+                    .min_by_key(|j| coordinates[**j].depth)
+                    .unwrap();
+                coordinates[open] = coordinates[*node].clone();
+                coordinates[i] = coordinates[*node].clone();
+            }
+        }
+
+        coordinates
+    }
+
+    fn tree_nodes(&self, input: &str) -> Vec<TreeNode> {
+        let (state, expr) = parse(input);
+        // the variables are numbered in the order of their first occurrence, as in `encode`
+        let mut vars = state
+            .iter()
+            .map(|(var, _)| var.as_str())
+            .collect::<Vec<_>>();
+        for var in expr.variables() {
+            if !vars.contains(&var) {
+                vars.push(var);
+            }
+        }
+
+        let mut nodes = Vec::new();
+        self.collect_tree_nodes(&expr, &state, &vars, &mut nodes);
+        nodes
+    }
+
+    fn dag_nodes(&self, input: &str) -> Vec<TreeNode> {
+        let (state, expr) = parse(input);
+        // the variables are numbered as in `tree_nodes`, and point to their nodes once added
+        let mut vars = state
+            .iter()
+            .map(|(var, _)| (var.as_str(), None))
+            .collect::<Vec<_>>();
+        for var in expr.variables() {
+            if !vars.iter().any(|(v, _)| *v == var) {
+                vars.push((var, None));
+            }
+        }
+
+        let mut nodes = Vec::new();
+        self.collect_dag_nodes(&expr, &state, &mut vars, &mut nodes);
+        nodes
+    }
+}
+
+impl MlmlTokenizer {
+    /// Adds the nodes of the expression to `nodes` and returns the index of its root.
+    fn collect_tree_nodes(
+        &self,
+        expr: &Expr,
+        state: &[(String, bool)],
+        vars: &[&str],
+        nodes: &mut Vec<TreeNode>,
+    ) -> usize {
+        let (token, value, children) = match expr {
+            Expr::Var(var) => {
+                let n = vars.iter().position(|v| v == var).unwrap();
+                let value = state
+                    .iter()
+                    .find(|(v, _)| v == var)
+                    .map(|(_, value)| *value);
+                (format!("<var{n}>"), value, vec![])
+            }
+            Expr::Const(value) => (
+                Notation::Unicode.const_symbol(*value).to_owned(),
+                None,
+                vec![],
+            ),
+            Expr::Not(e) => {
+                let child = self.collect_tree_nodes(e, state, vars, nodes);
+                (Notation::Unicode.not_symbol().to_owned(), None, vec![child])
+            }
+            Expr::BinaryOp(bop) => {
+                let l = self.collect_tree_nodes(&bop.l, state, vars, nodes);
+                let r = self.collect_tree_nodes(&bop.r, state, vars, nodes);
+                (
+                    bop.ty.symbol(Notation::Unicode).to_owned(),
+                    None,
+                    vec![l, r],
+                )
+            }
+            Expr::And(es) | Expr::Or(es) => {
+                // nested to the left, e.g. a ∧ b ∧ c as (a ∧ b) ∧ c
+                let (last, rest) = es.split_last().unwrap();
+                let (rest, ty) = if matches!(expr, Expr::And(_)) {
+                    (Expr::conjunction(rest.to_vec()), BinaryOpType::And)
+                } else {
+                    (Expr::disjunction(rest.to_vec()), BinaryOpType::Or)
+                };
+                let l = self.collect_tree_nodes(&rest, state, vars, nodes);
+                let r = self.collect_tree_nodes(last, state, vars, nodes);
+                (ty.symbol(Notation::Unicode).to_owned(), None, vec![l, r])
+            }
+        };
+
+        nodes.push(TreeNode {
+            token: self.vocab[token.as_str()],
+            value,
+            children,
+        });
+        nodes.len() - 1
+    }
+
+    /// Adds the nodes of the expression that aren't in `nodes` yet and returns the index of its
+    /// root.
+    fn collect_dag_nodes<'a>(
+        &self,
+        expr: &'a Expr,
+        state: &[(String, bool)],
+        vars: &mut [(&'a str, Option<usize>)],
+        nodes: &mut Vec<TreeNode>,
+    ) -> usize {
+        let (token, value, children) = match expr {
+            Expr::Var(var) => {
+                let n = vars.iter().position(|(v, _)| v == var).unwrap();
+                if let Some(node) = vars[n].1 {
+                    return node;
+                }
+                vars[n].1 = Some(nodes.len());
+                let value = state
+                    .iter()
+                    .find(|(v, _)| v == var)
+                    .map(|(_, value)| *value);
+                (format!("<var{n}>"), value, vec![])
+            }
+            Expr::Const(value) => (
+                Notation::Unicode.const_symbol(*value).to_owned(),
+                None,
+                vec![],
+            ),
+            Expr::Not(e) => {
+                let child = self.collect_dag_nodes(e, state, vars, nodes);
+                (Notation::Unicode.not_symbol().to_owned(), None, vec![child])
+            }
+            Expr::BinaryOp(bop) => {
+                let l = self.collect_dag_nodes(&bop.l, state, vars, nodes);
+                let r = self.collect_dag_nodes(&bop.r, state, vars, nodes);
+                (
+                    bop.ty.symbol(Notation::Unicode).to_owned(),
+                    None,
+                    vec![l, r],
+                )
+            }
+            Expr::And(es) | Expr::Or(es) => {
+                let children = es
+                    .iter()
+                    .map(|e| self.collect_dag_nodes(e, state, vars, nodes))
+                    .collect();
+                let ty = if matches!(expr, Expr::And(_)) {
+                    BinaryOpType::And
+                } else {
+                    BinaryOpType::Or
+                };
+                (ty.symbol(Notation::Unicode).to_owned(), None, children)
+            }
+        };
+
+        nodes.push(TreeNode {
+            token: self.vocab[token.as_str()],
+            value,
+            children,
+        });
+        nodes.len() - 1
+    }
+
+    /// Whether the token stands for a node of the expression: a variable, a constant or an
+    /// operator.
+    fn is_symbol(&self, token: usize) -> bool {
+        let token = &self.inv_vocab[&token];
+        token.starts_with("<var")
+            || CONSTANTS.contains(&token.as_str())
+            || OPERATORS.contains(&token.as_str())
+    }
+}
+
+/// Parses the state (if there is one) and the expression of the input.
+fn parse(input: &str) -> (Vec<(String, bool)>, Expr) {
+    let mut parser = Parser::new(input);
+    let state = if input.trim_start().starts_with('[') {
+        parser
+            .parse_state()
+            .unwrap_or_else(|e| panic!("invalid state: {e}"))
+    } else {
+        Vec::new()
+    };
+    let expr = parser
+        .parse()
+        .unwrap_or_else(|e| panic!("invalid expression: {e}"));
+
+    (state, expr)
+}
+
+fn starts_with(chars: &[char], s: &str) -> bool {
+    let len = s.chars().count();
+    chars.len() >= len && chars[..len].iter().copied().eq(s.chars())
 }
 
 #[cfg(test)]
 mod tests {
+    use mlml_dataset::sample::{Assignment, token_count};
+
     use super::*;
 
     #[test]
@@ -192,7 +516,170 @@ mod tests {
         let expr_with_state_str = "[i, f: true; g, j: false] ((g ∧ (¬j → i)) ∧ (f ∨ j))";
         let tokenizer = MlmlTokenizer::new(64, 4);
         let tokens = tokenizer.encode(expr_with_state_str);
+        assert_eq!(tokens[0], tokenizer.cls_token());
+        assert_eq!(tokens[1], tokenizer.vocab["["]);
         let decoded = tokenizer.decode(&tokens);
         println!("{decoded}");
     }
+
+    #[test]
+    fn constants() {
+        let tokenizer = MlmlTokenizer::new(64, 4);
+        let tokens = tokenizer.encode("[p: true] (p ∨ false) → ⊤");
+        assert_eq!(tokens, tokenizer.encode("[p: true] (p ∨ ⊥) → true"),);
+        assert_eq!(
+            tokens
+                .iter()
+                .filter(|t| **t == tokenizer.vocab["true"])
+                .count(),
+            1
+        );
+        assert_eq!(
+            tokens
+                .iter()
+                .filter(|t| **t == tokenizer.vocab["⊤"])
+                .count(),
+            1
+        );
+        assert_eq!(
+            tokens
+                .iter()
+                .filter(|t| **t == tokenizer.vocab["⊥"])
+                .count(),
+            1
+        );
+    }
+
+    #[test]
+    fn identifiers() {
+        let tokenizer = MlmlTokenizer::new(64, 4);
+        assert_eq!(
+            tokenizer.encode("[rain, x1: true; p_10: false] (rain ∧ ¬p_10) → x1"),
+            tokenizer.encode("[a, b: true; c: false] (a ∧ ¬c) → b"),
+        );
+    }
+
+    #[test]
+    fn state_free() {
+        let tokenizer = MlmlTokenizer::new(64, 4);
+        let tokens = tokenizer.encode("(p ∨ true) → q");
+        assert_eq!(tokens[0], tokenizer.cls_token());
+        assert_eq!(tokens[1], tokenizer.vocab["("]);
+        assert!(tokens.contains(&tokenizer.vocab["⊤"]));
+        assert!(!tokens.contains(&tokenizer.vocab["true"]));
+    }
+
+    #[test]
+    fn tree_coordinates() {
+        let tokenizer = MlmlTokenizer::new(64, 4);
+        let input = "[p: true; q: false] ¬p → (q ∨ p)";
+        let tokens = tokenizer.encode(input);
+        let coordinates = tokenizer.tree_coordinates(input);
+        let coordinates_of = |token: &str| {
+            let i = tokens
+                .iter()
+                .rposition(|t| *t == tokenizer.vocab[token])
+                .unwrap();
+            coordinates[i].clone()
+        };
+
+        // the state is outside the expression
+        assert_eq!(coordinates_of("["), TreeCoordinates::default());
+        assert_eq!(coordinates_of("<value_prefix>"), TreeCoordinates::default());
+
+        let implication = coordinates_of("→");
+        assert_eq!((implication.depth, implication.parent), (1, 0));
+        let implication_token = tokens.iter().position(|t| *t == tokenizer.vocab["→"]);
+        assert_eq!(Some(coordinates_of("∨").parent), implication_token);
+        assert_eq!(coordinates_of("∨").path, [1]);
+        // the last p, the right operand of the disjunction
+        let p = coordinates_of("<var0>");
+        assert_eq!((p.depth, p.path), (3, vec![1, 1]));
+        // the inner parentheses wrap the disjunction, and the outer ones the whole expression
+        assert_eq!(coordinates_of("("), coordinates_of("∨"));
+        assert_eq!(coordinates_of(")"), implication);
+        assert_eq!(coordinates[tokens.len() - 1], TreeCoordinates::default());
+    }
+
+    #[test]
+    fn tree_nodes() {
+        let tokenizer = MlmlTokenizer::new(64, 4);
+        let node = |token: &str, value, children: &[usize]| TreeNode {
+            token: tokenizer.vocab[token],
+            value,
+            children: children.to_vec(),
+        };
+
+        assert_eq!(
+            tokenizer.tree_nodes("[q: true] ¬q ∨ p ∨ ⊥"),
+            [
+                node("<var0>", Some(true), &[]),
+                node("¬", None, &[0]),
+                node("<var1>", None, &[]),
+                node("∨", None, &[1, 2]),
+                node("⊥", None, &[]),
+                node("∨", None, &[3, 4]),
+            ]
+        );
+    }
+
+    #[test]
+    fn dag_nodes() {
+        let tokenizer = MlmlTokenizer::new(64, 4);
+        let node = |token: &str, value, children: &[usize]| TreeNode {
+            token: tokenizer.vocab[token],
+            value,
+            children: children.to_vec(),
+        };
+
+        assert_eq!(
+            tokenizer.dag_nodes("[q: true] ¬q ∨ p ∨ (p → q)"),
+            [
+                node("<var0>", Some(true), &[]),
+                node("¬", None, &[0]),
+                node("<var1>", None, &[]),
+                node("→", None, &[2, 0]),
+                node("∨", None, &[1, 2, 3]),
+            ]
+        );
+    }
+
+    #[test]
+    fn token_counts() {
+        // mlml-dataset records the longest sample's length by counting its tokens itself
+        let tokenizer = MlmlTokenizer::new(64, 5);
+        for input in [
+            "[i, f: true; g, j: false] (g ∧ (¬j → i)) ∧ (f ∨ j)",
+            "[p: false] ¬¬p ↓ (p ↑ ⊤)",
+            "[a, b: true] a ⊕ b ⊕ a",
+            "(a ∧ b ∧ ¬c) ∨ (⊥ ↔ d) ∨ e",
+            "a & (!b -> c)",
+        ] {
+            let (state, expr) = parse(input);
+            let state = (!state.is_empty()).then(|| Assignment::new(state).unwrap());
+            for notation in [Notation::Unicode, Notation::Ascii] {
+                // the samples are encoded as mlml-dataset displays them
+                let expr_str = expr.to_string_with(notation);
+                let sample = match &state {
+                    Some(state) => format!("{state} {expr_str}"),
+                    None => expr_str,
+                };
+                let tokens = tokenizer.encode(&sample);
+                let length = tokens
+                    .iter()
+                    .filter(|t| **t != tokenizer.pad_token())
+                    .count();
+                assert_eq!(length, token_count(state.as_ref(), &expr), "{sample}");
+            }
+        }
+    }
+
+    #[test]
+    fn ascii_aliases() {
+        let tokenizer = MlmlTokenizer::new(64, 4);
+        assert_eq!(
+            tokenizer.encode("[i, f: true; g, j: false] (g & (!j -> i)) <-> (f || j)"),
+            tokenizer.encode("[i, f: true; g, j: false] (g ∧ (¬j → i)) ↔ (f ∨ j)"),
+        );
+    }
 }
@@ -0,0 +1,230 @@
+// The forward pass of burn's transformer encoder, extended with the position encodings that act
+// within the attention (rotary and ALiBi) rather than on the input embeddings. It reuses the
+// parameters of a `TransformerEncoder`, so the model's structure doesn't depend on the encoding.
+
+use burn::{
+    nn::{
+        Linear,
+        attention::MultiHeadAttention,
+        transformer::{TransformerEncoder, TransformerEncoderLayer},
+    },
+    prelude::*,
+    tensor::activation::{quiet_softmax, softmax},
+};
+use mlml_util::PositionEncoding;
+
+// The base of the rotary frequencies, as in the RoFormer paper
+const ROTARY_THETA: f32 = 10_000.0;
+
+/// The position information applied within the attention of every layer.
+struct RelativePositions<B: Backend> {
+    /// The cosines and sines of the rotary angles, `[1, 1, seq_length, d_k]` each.
+    rotation: Option<(Tensor<B, 4>, Tensor<B, 4>)>,
+    /// The ALiBi biases of the attention scores, `[1, n_heads, seq_length, seq_length]`.
+    bias: Option<Tensor<B, 4>>,
+}
+
+/// Applies the encoder to the embeddings `[batch_size, seq_length, d_model]`, which carry no
+/// position information; the rotary or ALiBi `position_encoding` provides it instead.
+pub fn forward<B: Backend>(
+    encoder: &TransformerEncoder<B>,
+    input: Tensor<B, 3>,
+    mask_pad: Tensor<B, 2, Bool>,
+    position_encoding: PositionEncoding,
+) -> Tensor<B, 3> {
+    let [_, seq_length, _] = input.dims();
+    let device = input.device();
+    let d_k = encoder.d_model / encoder.n_heads;
+
+    let positions = Tensor::<B, 1, Int>::arange(0..seq_length as i64, &device).float();
+    let positions = match position_encoding {
+        PositionEncoding::Rotary => RelativePositions {
+            rotation: Some(rotary_angles(positions, d_k)),
+            bias: None,
+        },
+        PositionEncoding::Alibi => RelativePositions {
+            rotation: None,
+            bias: Some(alibi_bias(positions, encoder.n_heads)),
+        },
+        PositionEncoding::Learned | PositionEncoding::Sinusoidal => {
+            panic!("the {position_encoding:?} position encoding applies to the input embeddings")
+        }
+    };
+
+    encoder.layers.iter().fold(input, |x, layer| {
+        layer_forward(layer, x, mask_pad.clone(), &positions)
+    })
+}
+
+// Mirrors `TransformerEncoderLayer::forward`.
+fn layer_forward<B: Backend>(
+    layer: &TransformerEncoderLayer<B>,
+    x: Tensor<B, 3>,
+    mask_pad: Tensor<B, 2, Bool>,
+    positions: &RelativePositions<B>,
+) -> Tensor<B, 3> {
+    // Multi-head attention residual path
+    let mut residual_path = x.clone();
+    if layer.norm_first {
+        residual_path = layer.norm_2.forward(residual_path);
+    }
+    let residual_path = self_attention(&layer.mha, residual_path, mask_pad, positions);
+    let residual_path = layer.dropout.forward(residual_path);
+    let mut x = x + residual_path;
+
+    // Feed forward residual path
+    let residual_path = if layer.norm_first {
+        layer.norm_1.forward(x.clone())
+    } else {
+        x = layer.norm_1.forward(x);
+        x.clone()
+    };
+    let residual_path = layer.pwff.forward(residual_path);
+    let residual_path = layer.dropout.forward(residual_path);
+    let mut x = x + residual_path;
+
+    if !layer.norm_first {
+        x = layer.norm_2.forward(x);
+    }
+
+    x
+}
+
+// Mirrors `MultiHeadAttention::forward` for self-attention.
+fn self_attention<B: Backend>(
+    mha: &MultiHeadAttention<B>,
+    x: Tensor<B, 3>,
+    mask_pad: Tensor<B, 2, Bool>,
+    positions: &RelativePositions<B>,
+) -> Tensor<B, 3> {
+    let [batch_size, seq_length, d_model] = x.dims();
+    let heads = |linear: &Linear<B>| {
+        linear
+            .forward(x.clone())
+            .reshape([batch_size, seq_length, mha.n_heads, mha.d_k])
+            .swap_dims(1, 2)
+    };
+
+    let mut query = heads(&mha.query);
+    let mut key = heads(&mha.key);
+    let value = heads(&mha.value);
+    if let Some((cos, sin)) = &positions.rotation {
+        query = rotate(query, cos, sin);
+        key = rotate(key, cos, sin);
+    }
+
+    let mut scores = query
+        .matmul(key.transpose())
+        .div_scalar((mha.d_k as f32).sqrt());
+    if let Some(bias) = &positions.bias {
+        scores = scores + bias.clone();
+    }
+    let scores = mha.dropout.forward(scores).mask_fill(
+        mask_pad.reshape([batch_size, 1, 1, seq_length]),
+        mha.min_float,
+    );
+    let weights = if mha.quiet_softmax {
+        quiet_softmax(scores, 3)
+    } else {
+        softmax(scores, 3)
+    };
+
+    let context = weights
+        .matmul(value)
+        .swap_dims(1, 2)
+        .reshape([batch_size, seq_length, d_model]);
+
+    mha.output.forward(context)
+}
+
+/// Returns the cosines and sines of the angles by which the rotary encoding rotates the pairs of
+/// query and key features at each position.
+fn rotary_angles<B: Backend>(positions: Tensor<B, 1>, d_k: usize) -> (Tensor<B, 4>, Tensor<B, 4>) {
+    let [seq_length] = positions.dims();
+    let frequencies = Tensor::<B, 1, Int>::arange_step(0..d_k as i64, 2, &positions.device())
+        .float()
+        .div_scalar(d_k as f32)
+        .mul_scalar(-ROTARY_THETA.ln())
+        .exp();
+    let angles = positions
+        .reshape([seq_length, 1])
+        .matmul(frequencies.reshape([1, d_k / 2]));
+    let angles = Tensor::cat(vec![angles.clone(), angles], 1).reshape([1, 1, seq_length, d_k]);
+
+    (angles.clone().cos(), angles.sin())
+}
+
+/// Rotates the features `[batch_size, n_heads, seq_length, d_k]`; the i-th feature is paired with
+/// the (i + d_k / 2)-th one.
+fn rotate<B: Backend>(x: Tensor<B, 4>, cos: &Tensor<B, 4>, sin: &Tensor<B, 4>) -> Tensor<B, 4> {
+    let half = x.dims()[3] / 2;
+    let first = x.clone().narrow(3, 0, half);
+    let second = x.clone().narrow(3, half, half);
+    let rotated = Tensor::cat(vec![second.neg(), first], 3);
+
+    x * cos.clone() + rotated * sin.clone()
+}
+
+/// Returns the ALiBi biases, which decrease linearly with the distance between the positions, at a
+/// different rate in each head.
+fn alibi_bias<B: Backend>(positions: Tensor<B, 1>, n_heads: usize) -> Tensor<B, 4> {
+    let [seq_length] = positions.dims();
+    let slopes = (1..=n_heads)
+        .map(|head| 2f32.powf(-8.0 * head as f32 / n_heads as f32))
+        .collect::<Vec<_>>();
+    let slopes = Tensor::<B, 1>::from_data(TensorData::new(slopes, [n_heads]), &positions.device())
+        .reshape([1, n_heads, 1, 1]);
+    let distances = (positions.clone().reshape([seq_length, 1])
+        - positions.reshape([1, seq_length]))
+    .abs()
+    .reshape([1, 1, seq_length, seq_length]);
+
+    (distances * slopes).neg()
+}
+
+#[cfg(test)]
+mod tests {
+    use burn::{backend::NdArray, tensor::Distribution};
+
+    use super::*;
+
+    type B = NdArray;
+
+    fn positions(seq_length: usize) -> Tensor<B, 1> {
+        Tensor::arange(0..seq_length as i64, &Default::default()).float()
+    }
+
+    #[test]
+    fn relative_positions() {
+        // the rotated scores of the same query and key only depend on the distance between them
+        let (cos, sin) = rotary_angles(positions(5), 8);
+        let query =
+            Tensor::<B, 4>::random([1, 1, 1, 8], Distribution::Default, &Default::default())
+                .repeat_dim(2, 5);
+        let key = Tensor::<B, 4>::random([1, 1, 1, 8], Distribution::Default, &Default::default())
+            .repeat_dim(2, 5);
+        let scores = rotate(query, &cos, &sin)
+            .matmul(rotate(key, &cos, &sin).transpose())
+            .reshape([5, 5])
+            .into_data()
+            .to_vec::<f32>()
+            .unwrap();
+        for (i, j) in [(0, 2), (1, 0), (3, 2)] {
+            let score = scores[i * 5 + j];
+            let shifted = scores[(i + 1) * 5 + j + 1];
+            assert!((score - shifted).abs() < 1e-4, "{score} vs {shifted}");
+        }
+
+        let bias = alibi_bias(positions(3), 2)
+            .into_data()
+            .to_vec::<f32>()
+            .unwrap();
+        assert_eq!(
+            &bias[..9],
+            [
+                0.0, -0.0625, -0.125, -0.0625, 0.0, -0.0625, -0.125, -0.0625, 0.0
+            ]
+        );
+        assert_eq!(bias[11], -0.00390625 * 2.0);
+    }
+}
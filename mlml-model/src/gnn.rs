@@ -0,0 +1,221 @@
+// This is a relational graph neural network over the expressions' DAGs, in which all the
+// occurrences of a variable share a single node. Each layer passes messages along the edges between
+// the nodes and their children, in both directions, and the expressions are classified from the
+// states of their roots along with the mean states of their nodes.
+
+use crate::{
+    data::{EDGE_RELATIONS, GraphBatch, InferenceBatch, TrainingBatch},
+    model::classification_output,
+};
+use burn::{
+    nn::{
+        Dropout, DropoutConfig, Embedding, EmbeddingConfig, LayerNorm, LayerNormConfig, Linear,
+        LinearConfig,
+    },
+    prelude::*,
+    tensor::{IndexingUpdateOp, activation::gelu},
+    train::ClassificationOutput,
+};
+
+// Define the model configuration
+#[derive(Debug, Config)]
+pub struct GnnModelConfig {
+    n_classes: usize,
+    vocab_size: usize,
+    d_model: usize,
+    // the number of message passing rounds; the information of a node reaches that many edges away
+    n_layers: usize,
+    #[config(default = 0.0)]
+    dropout: f64,
+}
+
+// Define the model structure
+#[derive(Module, Debug)]
+pub struct GnnModel<B: Backend> {
+    embedding_token: Embedding<B>,
+    // the values of the variables: unassigned, false or true
+    embedding_value: Embedding<B>,
+    layers: Vec<MessagePassingLayer<B>>,
+    // reads the states of the roots concatenated with the mean states of the nodes
+    output: Linear<B>,
+    d_model: usize,
+}
+
+/// A round of message passing along the edges of the graph
+#[derive(Module, Debug)]
+pub struct MessagePassingLayer<B: Backend> {
+    // the messages sent along each kind of edge, computed at once
+    messages: Linear<B>,
+    // combines each node's state with the mean of its incoming messages
+    update: Linear<B>,
+    norm: LayerNorm<B>,
+    dropout: Dropout,
+}
+
+// Define functions for model initialization
+impl GnnModelConfig {
+    /// Initializes a model with default weights
+    pub fn init<B: Backend>(&self, device: &B::Device) -> GnnModel<B> {
+        let layers = (0..self.n_layers)
+            .map(|_| MessagePassingLayer {
+                messages: LinearConfig::new(self.d_model, EDGE_RELATIONS * self.d_model)
+                    .init(device),
+                update: LinearConfig::new(2 * self.d_model, self.d_model).init(device),
+                norm: LayerNormConfig::new(self.d_model).init(device),
+                dropout: DropoutConfig::new(self.dropout).init(),
+            })
+            .collect();
+
+        GnnModel {
+            embedding_token: EmbeddingConfig::new(self.vocab_size, self.d_model).init(device),
+            embedding_value: EmbeddingConfig::new(3, self.d_model).init(device),
+            layers,
+            output: LinearConfig::new(2 * self.d_model, self.n_classes).init(device),
+            d_model: self.d_model,
+        }
+    }
+}
+
+impl<B: Backend> MessagePassingLayer<B> {
+    /// Updates the states of the nodes `[n_nodes, d_model]`, given the number of incoming edges of
+    /// each node `[n_nodes, 1]`
+    fn forward(
+        &self,
+        states: Tensor<B, 2>,
+        graph: &GraphBatch<B>,
+        in_degrees: Tensor<B, 2>,
+    ) -> Tensor<B, 2> {
+        let [n_nodes, d_model] = states.dims();
+
+        // the message of an edge is the transformation of its kind applied to its source's state
+        let edge_messages = graph.sources.clone() * EDGE_RELATIONS as i64 + graph.relations.clone();
+        let messages = self
+            .messages
+            .forward(states.clone())
+            .reshape([n_nodes * EDGE_RELATIONS, d_model])
+            .select(0, edge_messages);
+        let messages = Tensor::zeros([n_nodes, d_model], &states.device()).select_assign(
+            0,
+            graph.targets.clone(),
+            messages,
+            IndexingUpdateOp::Add,
+        ) / in_degrees;
+
+        let update = self
+            .update
+            .forward(Tensor::cat(vec![states.clone(), messages], 1));
+        self.norm
+            .forward(states + self.dropout.forward(gelu(update)))
+    }
+}
+
+/// Define model behavior
+impl<B: Backend> GnnModel<B> {
+    // Defines forward pass for training
+    pub fn forward(&self, item: TrainingBatch<B>) -> ClassificationOutput<B> {
+        let output = self.classify(item.graph);
+
+        classification_output(output, item.labels)
+    }
+
+    /// Defines forward pass for inference
+    pub fn infer(&self, item: InferenceBatch<B>) -> Tensor<B, 2> {
+        gelu(self.classify(item.graph))
+    }
+
+    /// Passes messages through the graph and returns the class scores of the expressions.
+    fn classify(&self, graph: Option<GraphBatch<B>>) -> Tensor<B, 2> {
+        let graph = graph.expect("the batches lack the DAGs of the expressions");
+        let device = &self.embedding_token.devices()[0];
+        let graph = GraphBatch {
+            tokens: graph.tokens.to_device(device),
+            values: graph.values.to_device(device),
+            sources: graph.sources.to_device(device),
+            targets: graph.targets.to_device(device),
+            relations: graph.relations.to_device(device),
+            graphs: graph.graphs.to_device(device),
+            roots: graph.roots.to_device(device),
+        };
+        let [n_nodes] = graph.tokens.dims();
+        let [batch_size] = graph.roots.dims();
+        let d_model = self.d_model;
+
+        // Embed the nodes' symbols along with the values of the variables
+        let tokens = graph.tokens.clone().reshape([1, n_nodes]);
+        let values = graph.values.clone().reshape([1, n_nodes]);
+        let mut states = (self.embedding_token.forward(tokens)
+            + self.embedding_value.forward(values))
+        .reshape([n_nodes, d_model]);
+
+        let [n_edges] = graph.targets.dims();
+        let in_degrees = count(graph.targets.clone(), n_edges, n_nodes, device).clamp_min(1.0);
+        for layer in &self.layers {
+            states = layer.forward(states, &graph, in_degrees.clone());
+        }
+
+        // Read out the roots and the means of the nodes of each expression
+        let sizes = count(graph.graphs.clone(), n_nodes, batch_size, device);
+        let means = Tensor::zeros([batch_size, d_model], device).select_assign(
+            0,
+            graph.graphs,
+            states.clone(),
+            IndexingUpdateOp::Add,
+        ) / sizes;
+        let roots = states.select(0, graph.roots);
+
+        self.output.forward(Tensor::cat(vec![roots, means], 1))
+    }
+}
+
+/// Counts the occurrences of each of the `n` indices among the `len` ones given, as `[n, 1]`.
+fn count<B: Backend>(
+    indices: Tensor<B, 1, Int>,
+    len: usize,
+    n: usize,
+    device: &B::Device,
+) -> Tensor<B, 2> {
+    Tensor::zeros([n, 1], device).select_assign(
+        0,
+        indices,
+        Tensor::ones([len, 1], device),
+        IndexingUpdateOp::Add,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use burn::{backend::NdArray, data::dataloader::batcher::Batcher};
+
+    use mlml_util::Architecture;
+
+    use super::*;
+    use crate::data::{MlmlBatcher, MlmlTokenizer, Tokenizer};
+
+    type B = NdArray;
+
+    #[test]
+    fn batching() {
+        // the scores of an expression don't depend on the others in its batch
+        let tokenizer = Arc::new(MlmlTokenizer::new(64, 4));
+        let batcher = MlmlBatcher::new(tokenizer.clone(), 64).with_architecture(Architecture::Gnn);
+        let model =
+            GnnModelConfig::new(3, tokenizer.vocab_size(), 8, 2).init::<B>(&Default::default());
+        let infer = |inputs: &[&str]| {
+            let batch: InferenceBatch<B> = batcher.batch(
+                inputs.iter().map(|input| input.to_string()).collect(),
+                &Default::default(),
+            );
+            model.infer(batch).into_data().to_vec::<f32>().unwrap()
+        };
+
+        let expr = "[a: true; c: false] ¬(a ∧ b ∧ c) → (b ↔ a)";
+        let alone = infer(&[expr]);
+        let batched = infer(&["a", expr, "(a ⊕ b) ∨ ¬¬a"]);
+        assert_eq!(batched.len(), 9);
+        for (a, b) in alone.iter().zip(&batched[3..6]) {
+            assert!((a - b).abs() < 1e-5, "{alone:?} vs {batched:?}");
+        }
+    }
+}
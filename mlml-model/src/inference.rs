@@ -3,7 +3,7 @@
 // and a batcher to prepare the input data. The model is then used to make predictions
 // on the input samples, and the results are printed out for each sample.
 
-use std::{str::FromStr, sync::Arc};
+use std::sync::Arc;
 
 use burn::{
     data::dataloader::batcher::Batcher,
@@ -14,8 +14,7 @@ use mlml_util::MlmlConfig;
 
 use crate::{
     data::{MlmlBatcher, MlmlDataset, MlmlTokenizer, Tokenizer},
-    model::MlmlModelConfig,
-    training::ExperimentConfig,
+    training::{ExperimentConfig, init_model},
 };
 
 // Define inference function
@@ -35,14 +34,15 @@ pub fn infer<B: Backend, D: MlmlDataset + 'static>(
         mlml_config.dataset.max_variables,
     ));
 
-    // Get number of classes from dataset
-    let n_classes = 2;
+    // Get the classes of the task
+    let classes = mlml_config.dataset.task.classes();
 
     // Initialize batcher for batching samples
-    let batcher = Arc::new(MlmlBatcher::new(
-        tokenizer.clone(),
-        mlml_config.dataset.max_seq_length,
-    ));
+    let batcher = Arc::new(
+        MlmlBatcher::new(tokenizer.clone(), mlml_config.dataset.max_seq_length)
+            .with_tree_positions(mlml_config.model.tree_positions)
+            .with_architecture(mlml_config.model.architecture),
+    );
 
     // Load pre-trained model weights
     println!("Loading weights ...");
@@ -52,14 +52,8 @@ pub fn infer<B: Backend, D: MlmlDataset + 'static>(
 
     // Create model using loaded weights
     println!("Creating model ...");
-    let model = MlmlModelConfig::new(
-        config.transformer,
-        n_classes,
-        tokenizer.vocab_size(),
-        mlml_config.dataset.max_seq_length,
-    )
-    .init::<B>(&device)
-    .load_record(record); // Initialize model with loaded weights
+    let model =
+        init_model::<B>(&config, &mlml_config, tokenizer.vocab_size(), &device).load_record(record); // Initialize model with loaded weights
 
     // Run inference on the given text samples
     println!("Running inference ...");
@@ -76,9 +70,9 @@ pub fn infer<B: Backend, D: MlmlDataset + 'static>(
         let prediction = predictions.clone().slice([i..i + 1]); // Get prediction for current sample
         let logits = prediction.to_data(); // Convert prediction tensor to data
         let class_index = prediction.argmax(1).squeeze_dim::<1>(1).into_scalar(); // Get class index with the highest value
-        let class = (class_index.elem::<i32>() as usize) == 1; // Get class name
+        let class = classes[class_index.elem::<i32>() as usize]; // Get class name
 
-        let correct = <bool>::from_str(expected_ret).unwrap() == class;
+        let correct = expected_ret == class;
         let marker = if correct { "" } else { "in" };
 
         // Print sample text, predicted logits and predicted class
@@ -2,9 +2,12 @@
 extern crate derive_new;
 
 mod data;
+mod encoder;
+mod gnn;
 mod model;
+mod tree_lstm;
 
 pub mod inference;
 pub mod training;
 
-pub use data::{MlmlDataset, RawDataset};
+pub use data::{MlmlDataset, RawDataset, check_dataset, load_metadata};
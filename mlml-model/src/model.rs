@@ -3,17 +3,31 @@
 // The model is then trained using Cross-Entropy loss. It contains methods for model initialization
 // (both with and without pre-trained weights), forward pass, inference, training, and validation.
 
-use crate::data::{InferenceBatch, TrainingBatch};
+use crate::{
+    data::{InferenceBatch, MAX_TREE_BRANCHING, MAX_TREE_DEPTH, TrainingBatch, TreeBatch},
+    encoder,
+    gnn::GnnModel,
+    tree_lstm::TreeLstmModel,
+};
 use burn::{
+    module::Ignored,
     nn::{
-        Embedding, EmbeddingConfig, Linear, LinearConfig,
+        Embedding, EmbeddingConfig, Linear, LinearConfig, generate_sinusoids,
         loss::CrossEntropyLossConfig,
         transformer::{TransformerEncoder, TransformerEncoderConfig, TransformerEncoderInput},
     },
     prelude::*,
-    tensor::{activation::gelu, backend::AutodiffBackend},
+    tensor::{
+        activation::{gelu, softmax},
+        backend::AutodiffBackend,
+    },
     train::{ClassificationOutput, InferenceStep, TrainOutput, TrainStep},
 };
+use mlml_util::{Pooling, PositionEncoding};
+
+// Stands in for negative infinity at the padding positions, so that they are ignored by max and
+// attention pooling
+const MASK_VALUE: f32 = -1.0e4;
 
 // Define the model configuration
 #[derive(Debug, Config)]
@@ -22,6 +36,12 @@ pub struct MlmlModelConfig {
     n_classes: usize,
     vocab_size: usize,
     max_seq_length: usize,
+    #[config(default = "Pooling::Cls")]
+    pooling: Pooling,
+    #[config(default = "PositionEncoding::Learned")]
+    position_encoding: PositionEncoding,
+    #[config(default = false)]
+    tree_positions: bool,
 }
 
 // Define the model structure
@@ -29,12 +49,73 @@ pub struct MlmlModelConfig {
 pub struct MlmlModel<B: Backend> {
     transformer: TransformerEncoder<B>,
     embedding_token: Embedding<B>,
-    embedding_pos: Embedding<B>,
+    // only used by the learned position encoding
+    embedding_pos: Option<Embedding<B>>,
+    embedding_tree: Option<TreeEmbedding<B>>,
+    // scores the positions; only used by attention pooling
+    attention_pooling: Option<Linear<B>>,
     output: Linear<B>,
+    pooling: Ignored<Pooling>,
+    position_encoding: Ignored<PositionEncoding>,
     n_classes: usize,
     max_seq_length: usize,
 }
 
+/// Embeds the coordinates of the tokens in the parse trees of their expressions
+#[derive(Module, Debug)]
+pub struct TreeEmbedding<B: Backend> {
+    depth: Embedding<B>,
+    // one embedding per level and child index; the embeddings of the levels of a path are summed
+    path: Embedding<B>,
+    // projects the token embedding of the parent
+    parent: Linear<B>,
+}
+
+impl<B: Backend> TreeEmbedding<B> {
+    fn init(d_model: usize, device: &B::Device) -> Self {
+        Self {
+            depth: EmbeddingConfig::new(MAX_TREE_DEPTH + 1, d_model).init(device),
+            path: EmbeddingConfig::new(MAX_TREE_DEPTH * (MAX_TREE_BRANCHING + 1), d_model)
+                .init(device),
+            parent: LinearConfig::new(d_model, d_model).init(device),
+        }
+    }
+
+    /// Embeds the coordinates given the token embeddings `[batch_size, seq_length, d_model]`
+    fn forward(&self, tree: TreeBatch<B>, embedding_tokens: Tensor<B, 3>) -> Tensor<B, 3> {
+        let [batch_size, seq_length, d_model] = embedding_tokens.dims();
+        let device = &embedding_tokens.device();
+
+        let depth = self.depth.forward(tree.depths.to_device(device));
+
+        let level_offsets = Tensor::<B, 1, Int>::arange_step(
+            0..(MAX_TREE_DEPTH * (MAX_TREE_BRANCHING + 1)) as i64,
+            MAX_TREE_BRANCHING + 1,
+            device,
+        )
+        .reshape([1, 1, MAX_TREE_DEPTH]);
+        let path_indices = (tree.paths.to_device(device) + level_offsets)
+            .reshape([batch_size, seq_length * MAX_TREE_DEPTH]);
+        let path = self
+            .path
+            .forward(path_indices)
+            .reshape([batch_size, seq_length, MAX_TREE_DEPTH, d_model])
+            .sum_dim(2)
+            .reshape([batch_size, seq_length, d_model]);
+
+        let parent_indices = tree
+            .parents
+            .to_device(device)
+            .unsqueeze_dim::<3>(2)
+            .expand([batch_size, seq_length, d_model]);
+        let parent = self
+            .parent
+            .forward(embedding_tokens.gather(1, parent_indices));
+
+        depth + path + parent
+    }
+}
+
 // Define functions for model initialization
 impl MlmlModelConfig {
     /// Initializes a model with default weights
@@ -43,14 +124,30 @@ impl MlmlModelConfig {
         let transformer = self.transformer.init(device);
         let embedding_token =
             EmbeddingConfig::new(self.vocab_size, self.transformer.d_model).init(device);
-        let embedding_pos =
-            EmbeddingConfig::new(self.max_seq_length, self.transformer.d_model).init(device);
+        let embedding_pos = (self.position_encoding == PositionEncoding::Learned).then(|| {
+            EmbeddingConfig::new(self.max_seq_length, self.transformer.d_model).init(device)
+        });
+        if self.position_encoding == PositionEncoding::Rotary {
+            assert!(
+                (self.transformer.d_model / self.transformer.n_heads).is_multiple_of(2),
+                "the rotary position encoding requires an even d_model / n_heads"
+            );
+        }
+        let embedding_tree = self
+            .tree_positions
+            .then(|| TreeEmbedding::init(self.transformer.d_model, device));
+        let attention_pooling = (self.pooling == Pooling::Attention)
+            .then(|| LinearConfig::new(self.transformer.d_model, 1).init(device));
 
         MlmlModel {
             transformer,
             embedding_token,
             embedding_pos,
+            embedding_tree,
+            attention_pooling,
             output,
+            pooling: Ignored(self.pooling),
+            position_encoding: Ignored(self.position_encoding),
             n_classes: self.n_classes,
             max_seq_length: self.max_seq_length,
         }
@@ -61,80 +158,159 @@ impl MlmlModelConfig {
 impl<B: Backend> MlmlModel<B> {
     // Defines forward pass for training
     pub fn forward(&self, item: TrainingBatch<B>) -> ClassificationOutput<B> {
-        // Get batch and sequence length, and the device
-        let [batch_size, seq_length] = item.tokens.dims();
-        let device = &self.embedding_token.devices()[0];
+        let output_classification = self.classify(item.tokens, item.mask_pad, item.tree);
 
-        // Move tensors to the correct device
-        let tokens = item.tokens.to_device(device);
-        let labels = item.labels.to_device(device);
-        let mask_pad = item.mask_pad.to_device(device);
-
-        // Calculate token and position embeddings, and combine them
-        let index_positions = Tensor::arange(0..seq_length as i64, device)
-            .reshape([1, seq_length])
-            .repeat_dim(0, batch_size);
-        let embedding_positions = self.embedding_pos.forward(index_positions);
-        let embedding_tokens = self.embedding_token.forward(tokens);
-        let embedding = (embedding_positions + embedding_tokens) / 2;
-
-        // Perform transformer encoding, calculate output and loss
-        let encoded = self
-            .transformer
-            .forward(TransformerEncoderInput::new(embedding).mask_pad(mask_pad));
-        let output = self.output.forward(encoded);
-
-        let output_classification = output
-            .slice([0..batch_size, 0..1])
-            .reshape([batch_size, self.n_classes]);
-
-        let loss = CrossEntropyLossConfig::new()
-            // .with_smoothing(Some(0.1))
-            // .with_weights(Some(vec![0.5, 0.5]))
-            .init(&output_classification.device())
-            .forward(output_classification.clone(), labels.clone());
-
-        // Return the output and loss
-        ClassificationOutput {
-            loss,
-            output: output_classification,
-            targets: labels,
-        }
+        classification_output(output_classification, item.labels)
     }
 
     /// Defines forward pass for inference
     pub fn infer(&self, item: InferenceBatch<B>) -> Tensor<B, 2> {
+        let output = self.classify(item.tokens, item.mask_pad, item.tree);
+
+        gelu(output)
+    }
+
+    /// Encodes the sequences and returns their class scores.
+    fn classify(
+        &self,
+        tokens: Tensor<B, 2, Int>,
+        mask_pad: Tensor<B, 2, Bool>,
+        tree: Option<TreeBatch<B>>,
+    ) -> Tensor<B, 2> {
         // Get batch and sequence length, and the device
-        let [batch_size, seq_length] = item.tokens.dims();
+        let [batch_size, seq_length] = tokens.dims();
         let device = &self.embedding_token.devices()[0];
 
         // Move tensors to the correct device
-        let tokens = item.tokens.to_device(device);
-        let mask_pad = item.mask_pad.to_device(device);
-
-        // Calculate token and position embeddings, and combine them
-        let index_positions = Tensor::arange(0..seq_length as i64, device)
-            .reshape([1, seq_length])
-            .repeat_dim(0, batch_size);
-        let embedding_positions = self.embedding_pos.forward(index_positions);
-        let embedding_tokens = self.embedding_token.forward(tokens);
-        let embedding = (embedding_positions + embedding_tokens) / 2;
-
-        // Perform transformer encoding, calculate output and apply softmax for prediction
-        let encoded = self
-            .transformer
-            .forward(TransformerEncoderInput::new(embedding).mask_pad(mask_pad));
-        let output = self.output.forward(encoded);
-        let output = output
-            .slice([0..batch_size, 0..1])
-            .reshape([batch_size, self.n_classes]);
+        let tokens = tokens.to_device(device);
+        let mask_pad = mask_pad.to_device(device);
 
-        gelu(output)
+        let mut embedding_tokens = self.embedding_token.forward(tokens);
+        if let Some(embedding_tree) = &self.embedding_tree {
+            let tree = tree.expect("the batches lack the tree coordinates of the tokens");
+            embedding_tokens =
+                embedding_tokens.clone() + embedding_tree.forward(tree, embedding_tokens);
+        }
+
+        // Perform transformer encoding, either of the token embeddings combined with the position
+        // embeddings, or with the positions applied within the attention
+        let encoded = match self.position_encoding.0 {
+            PositionEncoding::Learned | PositionEncoding::Sinusoidal => {
+                let embedding_positions = match &self.embedding_pos {
+                    Some(embedding_pos) => {
+                        let index_positions = Tensor::arange(0..seq_length as i64, device)
+                            .reshape([1, seq_length])
+                            .repeat_dim(0, batch_size);
+                        embedding_pos.forward(index_positions)
+                    }
+                    None => {
+                        generate_sinusoids(seq_length, self.transformer.d_model, 10_000, device)
+                            .unsqueeze()
+                    }
+                };
+                let embedding = (embedding_positions + embedding_tokens) / 2;
+
+                self.transformer
+                    .forward(TransformerEncoderInput::new(embedding).mask_pad(mask_pad.clone()))
+            }
+            PositionEncoding::Rotary | PositionEncoding::Alibi => encoder::forward(
+                &self.transformer,
+                embedding_tokens,
+                mask_pad.clone(),
+                self.position_encoding.0,
+            ),
+        };
+
+        // Pool the outputs of each sequence and classify them
+        let pooled = self.pool(encoded, mask_pad);
+
+        self.output.forward(pooled)
+    }
+
+    /// Reduces the encoder outputs `[batch_size, seq_length, d_model]` to `[batch_size, d_model]`.
+    fn pool(&self, encoded: Tensor<B, 3>, mask_pad: Tensor<B, 2, Bool>) -> Tensor<B, 2> {
+        let [batch_size, seq_length, d_model] = encoded.dims();
+        let mask_pad = mask_pad.unsqueeze_dim::<3>(2);
+
+        let pooled = match self.pooling.0 {
+            Pooling::Cls => encoded.slice([0..batch_size, 0..1]),
+            Pooling::Mean => {
+                let weights = mask_pad.bool_not().float();
+                (encoded * weights.clone()).sum_dim(1) / weights.sum_dim(1)
+            }
+            Pooling::Max => encoded
+                .mask_fill(
+                    mask_pad.expand([batch_size, seq_length, d_model]),
+                    MASK_VALUE,
+                )
+                .max_dim(1),
+            Pooling::Attention => {
+                let scores = self
+                    .attention_pooling
+                    .as_ref()
+                    .unwrap()
+                    .forward(encoded.clone())
+                    .mask_fill(mask_pad, MASK_VALUE);
+                (encoded * softmax(scores, 1)).sum_dim(1)
+            }
+        };
+
+        pooled.reshape([batch_size, d_model])
+    }
+}
+
+/// Calculates the loss of the class scores and returns them along with it
+pub(crate) fn classification_output<B: Backend>(
+    output_classification: Tensor<B, 2>,
+    labels: Tensor<B, 1, Int>,
+) -> ClassificationOutput<B> {
+    let labels = labels.to_device(&output_classification.device());
+
+    let loss = CrossEntropyLossConfig::new()
+        // .with_smoothing(Some(0.1))
+        // .with_weights(Some(vec![0.5, 0.5]))
+        .init(&output_classification.device())
+        .forward(output_classification.clone(), labels.clone());
+
+    // Return the output and loss
+    ClassificationOutput {
+        loss,
+        output: output_classification,
+        targets: labels,
+    }
+}
+
+/// A model of any of the configurable architectures
+#[derive(Module, Debug)]
+#[allow(clippy::large_enum_variant)] // there is only ever one model
+pub enum Classifier<B: Backend> {
+    Transformer(MlmlModel<B>),
+    TreeLstm(TreeLstmModel<B>),
+    Gnn(GnnModel<B>),
+}
+
+impl<B: Backend> Classifier<B> {
+    // Defines forward pass for training
+    pub fn forward(&self, item: TrainingBatch<B>) -> ClassificationOutput<B> {
+        match self {
+            Classifier::Transformer(model) => model.forward(item),
+            Classifier::TreeLstm(model) => model.forward(item),
+            Classifier::Gnn(model) => model.forward(item),
+        }
+    }
+
+    /// Defines forward pass for inference
+    pub fn infer(&self, item: InferenceBatch<B>) -> Tensor<B, 2> {
+        match self {
+            Classifier::Transformer(model) => model.infer(item),
+            Classifier::TreeLstm(model) => model.infer(item),
+            Classifier::Gnn(model) => model.infer(item),
+        }
     }
 }
 
 /// Define training step
-impl<B: AutodiffBackend> TrainStep for MlmlModel<B> {
+impl<B: AutodiffBackend> TrainStep for Classifier<B> {
     type Input = TrainingBatch<B>;
     type Output = ClassificationOutput<B>;
 
@@ -148,7 +324,7 @@ impl<B: AutodiffBackend> TrainStep for MlmlModel<B> {
 }
 
 /// Define validation step
-impl<B: Backend> InferenceStep for MlmlModel<B> {
+impl<B: Backend> InferenceStep for Classifier<B> {
     type Input = TrainingBatch<B>;
     type Output = ClassificationOutput<B>;
 
@@ -157,3 +333,75 @@ impl<B: Backend> InferenceStep for MlmlModel<B> {
         self.forward(item)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use burn::{backend::NdArray, data::dataloader::batcher::Batcher};
+
+    use super::*;
+    use crate::data::{MlmlBatcher, MlmlTokenizer, Tokenizer};
+
+    type B = NdArray;
+
+    fn model(pooling: Pooling) -> MlmlModel<B> {
+        MlmlModelConfig::new(TransformerEncoderConfig::new(2, 4, 1, 1), 2, 4, 3)
+            .with_pooling(pooling)
+            .init(&Default::default())
+    }
+
+    #[test]
+    fn pooling() {
+        let device = Default::default();
+        let encoded =
+            Tensor::<B, 3>::from_data([[[1.0, -2.0], [3.0, -4.0], [100.0, 100.0]]], &device);
+        // the last position is padding
+        let mask_pad = Tensor::<B, 2, Bool>::from_data([[false, false, true]], &device);
+        let pool = |pooling| {
+            model(pooling)
+                .pool(encoded.clone(), mask_pad.clone())
+                .into_data()
+                .to_vec::<f32>()
+                .unwrap()
+        };
+
+        assert_eq!(pool(Pooling::Cls), [1.0, -2.0]);
+        assert_eq!(pool(Pooling::Mean), [2.0, -3.0]);
+        assert_eq!(pool(Pooling::Max), [3.0, -2.0]);
+        let attention = pool(Pooling::Attention);
+        assert!((1.0..=3.0).contains(&attention[0]));
+        assert!((-4.0..=-2.0).contains(&attention[1]));
+    }
+
+    #[test]
+    fn longer_inputs() {
+        // the model is configured with a smaller max_seq_length than the tokenizer's, which only
+        // the learned position encoding is limited to
+        let tokenizer = Arc::new(MlmlTokenizer::new(40, 3));
+        let batch: InferenceBatch<B> = MlmlBatcher::new(tokenizer.clone(), 40)
+            .with_tree_positions(true)
+            .batch(
+                vec!["[a: true; b: false] ¬a ∨ (b → a)".into(), "a ∧ c".into()],
+                &Default::default(),
+            );
+
+        for (position_encoding, tree_positions) in [
+            (PositionEncoding::Sinusoidal, false),
+            (PositionEncoding::Rotary, false),
+            (PositionEncoding::Alibi, false),
+            (PositionEncoding::Alibi, true),
+        ] {
+            let model = MlmlModelConfig::new(
+                TransformerEncoderConfig::new(4, 4, 2, 1),
+                2,
+                tokenizer.vocab_size(),
+                3,
+            )
+            .with_position_encoding(position_encoding)
+            .with_tree_positions(tree_positions)
+            .init::<B>(&Default::default());
+            assert_eq!(model.infer(batch.clone()).dims(), [2, 2]);
+        }
+    }
+}
@@ -23,11 +23,13 @@ use burn::{
         },
     },
 };
-use mlml_util::MlmlConfig;
+use mlml_util::{Architecture, MlmlConfig};
 
 use crate::{
     data::{MlmlBatcher, MlmlDataset, MlmlTokenizer, Tokenizer},
-    model::MlmlModelConfig,
+    gnn::GnnModelConfig,
+    model::{Classifier, MlmlModelConfig},
+    tree_lstm::TreeLstmModelConfig,
 };
 
 // Define configuration struct for the experiment
@@ -37,6 +39,45 @@ pub struct ExperimentConfig {
     pub optimizer: AdamWConfig,
 }
 
+/// Initializes a model of the configured architecture with default weights
+pub(crate) fn init_model<B: Backend>(
+    config: &ExperimentConfig,
+    mlml_config: &MlmlConfig,
+    vocab_size: usize,
+    device: &B::Device,
+) -> Classifier<B> {
+    let n_classes = mlml_config.dataset.task.classes().len();
+
+    match mlml_config.model.architecture {
+        Architecture::Transformer => Classifier::Transformer(
+            MlmlModelConfig::new(
+                config.transformer.clone(),
+                n_classes,
+                vocab_size,
+                mlml_config.dataset.max_seq_length,
+            )
+            .with_pooling(mlml_config.model.pooling)
+            .with_position_encoding(mlml_config.model.position_encoding)
+            .with_tree_positions(mlml_config.model.tree_positions)
+            .init(device),
+        ),
+        Architecture::TreeLstm => Classifier::TreeLstm(
+            TreeLstmModelConfig::new(n_classes, vocab_size, config.transformer.d_model)
+                .init(device),
+        ),
+        Architecture::Gnn => Classifier::Gnn(
+            GnnModelConfig::new(
+                n_classes,
+                vocab_size,
+                config.transformer.d_model,
+                config.transformer.n_layers,
+            )
+            .with_dropout(config.transformer.dropout)
+            .init(device),
+        ),
+    }
+}
+
 // Define train function
 pub fn train<B: AutodiffBackend, D: MlmlDataset + 'static>(
     devices: Vec<B::Device>, // Device on which to perform computation (e.g., CPU or CUDA device)
@@ -53,16 +94,12 @@ pub fn train<B: AutodiffBackend, D: MlmlDataset + 'static>(
     ));
 
     // Initialize batcher
-    let batcher = MlmlBatcher::new(tokenizer.clone(), mlml_config.dataset.max_seq_length);
+    let batcher = MlmlBatcher::new(tokenizer.clone(), mlml_config.dataset.max_seq_length)
+        .with_tree_positions(mlml_config.model.tree_positions)
+        .with_architecture(mlml_config.model.architecture);
 
     // Initialize model
-    let model = MlmlModelConfig::new(
-        config.transformer.clone(),
-        2,
-        tokenizer.vocab_size(),
-        mlml_config.dataset.max_seq_length,
-    )
-    .init::<B>(&devices[0]);
+    let model = init_model::<B>(&config, &mlml_config, tokenizer.vocab_size(), &devices[0]);
 
     // Initialize data loaders for training and testing data
     let dataloader_train = DataLoaderBuilder::new(batcher.clone())
@@ -0,0 +1,157 @@
+// This is a binary Tree-LSTM (Tai et al., 2015) that serves as a structural baseline for the
+// transformer. Instead of reading the tokens in order, it composes the states of the nodes of the
+// expressions' parse trees bottom-up, and classifies each expression from the state of its root.
+
+use crate::{
+    data::{ExprBatch, InferenceBatch, TrainingBatch},
+    model::classification_output,
+};
+use burn::{
+    nn::{Embedding, EmbeddingConfig, Linear, LinearConfig},
+    prelude::*,
+    tensor::activation::{gelu, sigmoid, tanh},
+    train::ClassificationOutput,
+};
+
+// Define the model configuration
+#[derive(Debug, Config)]
+pub struct TreeLstmModelConfig {
+    n_classes: usize,
+    vocab_size: usize,
+    d_model: usize,
+}
+
+// Define the model structure
+#[derive(Module, Debug)]
+pub struct TreeLstmModel<B: Backend> {
+    embedding_token: Embedding<B>,
+    // the values of the variables: unassigned, false or true
+    embedding_value: Embedding<B>,
+    // the input, forget (one per child), output and update gates, computed from the node itself
+    // and from its children's hidden states
+    gates_node: Linear<B>,
+    gates_children: Linear<B>,
+    output: Linear<B>,
+    d_model: usize,
+}
+
+// Define functions for model initialization
+impl TreeLstmModelConfig {
+    /// Initializes a model with default weights
+    pub fn init<B: Backend>(&self, device: &B::Device) -> TreeLstmModel<B> {
+        TreeLstmModel {
+            embedding_token: EmbeddingConfig::new(self.vocab_size, self.d_model).init(device),
+            embedding_value: EmbeddingConfig::new(3, self.d_model).init(device),
+            gates_node: LinearConfig::new(self.d_model, 5 * self.d_model).init(device),
+            gates_children: LinearConfig::new(2 * self.d_model, 5 * self.d_model)
+                .with_bias(false)
+                .init(device),
+            output: LinearConfig::new(self.d_model, self.n_classes).init(device),
+            d_model: self.d_model,
+        }
+    }
+}
+
+/// Define model behavior
+impl<B: Backend> TreeLstmModel<B> {
+    // Defines forward pass for training
+    pub fn forward(&self, item: TrainingBatch<B>) -> ClassificationOutput<B> {
+        let output = self.classify(item.expr);
+
+        classification_output(output, item.labels)
+    }
+
+    /// Defines forward pass for inference
+    pub fn infer(&self, item: InferenceBatch<B>) -> Tensor<B, 2> {
+        gelu(self.classify(item.expr))
+    }
+
+    /// Composes the states of the nodes level by level and returns the class scores of the roots.
+    fn classify(&self, expr: Option<ExprBatch<B>>) -> Tensor<B, 2> {
+        let expr = expr.expect("the batches lack the parse trees of the expressions");
+        let device = &self.embedding_token.devices()[0];
+        let d_model = self.d_model;
+        let n_nodes = expr.level_sizes.iter().sum();
+
+        // Embed the nodes' symbols along with the values of the variables
+        let tokens = expr.tokens.to_device(device).reshape([1, n_nodes]);
+        let values = expr.values.to_device(device).reshape([1, n_nodes]);
+        let embedding = (self.embedding_token.forward(tokens)
+            + self.embedding_value.forward(values))
+        .reshape([n_nodes, d_model]);
+        let children = expr.children.to_device(device);
+
+        // The states of the nodes in the order of their numbers, after the zero states that stand
+        // in for missing children
+        let mut hidden = Tensor::zeros([1, d_model], device);
+        let mut cell = Tensor::zeros([1, d_model], device);
+        let mut start = 0;
+        for size in expr.level_sizes {
+            let range = start..start + size;
+            let child_numbers = children.clone().slice([range.clone()]).reshape([size * 2]);
+            let child_hidden = hidden
+                .clone()
+                .select(0, child_numbers.clone())
+                .reshape([size, 2 * d_model]);
+            let child_cell = cell
+                .clone()
+                .select(0, child_numbers)
+                .reshape([size, 2 * d_model]);
+
+            let gates = self.gates_node.forward(embedding.clone().slice([range]))
+                + self.gates_children.forward(child_hidden);
+            let [input, forget_l, forget_r, output, update] = gates.chunk(5, 1).try_into().unwrap();
+
+            let level_cell = sigmoid(input) * tanh(update)
+                + sigmoid(forget_l) * child_cell.clone().narrow(1, 0, d_model)
+                + sigmoid(forget_r) * child_cell.narrow(1, d_model, d_model);
+            let level_hidden = sigmoid(output) * tanh(level_cell.clone());
+
+            hidden = Tensor::cat(vec![hidden, level_hidden], 0);
+            cell = Tensor::cat(vec![cell, level_cell], 0);
+            start += size;
+        }
+
+        let roots = hidden.select(0, expr.roots.to_device(device));
+        self.output.forward(roots)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use burn::{backend::NdArray, data::dataloader::batcher::Batcher};
+
+    use mlml_util::Architecture;
+
+    use super::*;
+    use crate::data::{MlmlBatcher, MlmlTokenizer, Tokenizer};
+
+    type B = NdArray;
+
+    #[test]
+    fn batching() {
+        // the scores of an expression don't depend on the others in its batch
+        let tokenizer = Arc::new(MlmlTokenizer::new(64, 4));
+        let batcher =
+            MlmlBatcher::new(tokenizer.clone(), 64).with_architecture(Architecture::TreeLstm);
+        let model =
+            TreeLstmModelConfig::new(3, tokenizer.vocab_size(), 8).init::<B>(&Default::default());
+        let infer = |inputs: &[&str]| {
+            let batch: InferenceBatch<B> = batcher.batch(
+                inputs.iter().map(|input| input.to_string()).collect(),
+                &Default::default(),
+            );
+            model.infer(batch).into_data().to_vec::<f32>().unwrap()
+        };
+
+        let expr = "¬(a ∧ b ∧ c) → (b ↔ ⊤)";
+        let alone = infer(&[expr]);
+        let batched = infer(&["a", "[a: true] (a ⊕ b) ∨ ¬¬a", expr]);
+        assert_eq!(batched.len(), 9);
+        for (a, b) in alone.iter().zip(&batched[6..]) {
+            assert!((a - b).abs() < 1e-5, "{alone:?} vs {batched:?}");
+        }
+    }
+}
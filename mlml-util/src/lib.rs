@@ -1,5 +1,6 @@
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use std::{
+    collections::BTreeMap,
     path::{Path, PathBuf},
     process::Command,
     str,
@@ -14,7 +15,7 @@ pub struct MlmlConfig {
 }
 
 #[allow(dead_code)]
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct DatasetConfig {
     pub train_samples_count: usize,
     pub valid_samples_count: usize,
@@ -23,11 +24,379 @@ pub struct DatasetConfig {
     pub max_variables: usize,
     pub max_depth: usize,
     pub db_path: PathBuf,
+    #[serde(default)]
+    pub ascii_notation: bool,
+    #[serde(default)]
+    pub const_probability: f64,
+    #[serde(default = "default_variable_pool_size")]
+    pub variable_pool_size: usize,
+    #[serde(default = "default_max_arity")]
+    pub max_arity: usize,
+    #[serde(default)]
+    pub task: Task,
+    /// Skip expressions containing a trivial redundancy, such as `¬¬a` or `a ∧ a`.
+    #[serde(default)]
+    pub reject_reducible: bool,
+    /// Skip expressions that are logically equivalent to one already in another split, up to a
+    /// renaming of their variables; expressions with too many variables for a truth table (over
+    /// 20) aren't checked.
+    #[serde(default)]
+    pub reject_equivalent_across_splits: bool,
+    /// Deduplicate samples that only differ by a renaming of their variables, which the tokenizer
+    /// maps to the same tokens.
+    #[serde(default)]
+    pub dedup_alpha_equivalent: bool,
+    #[serde(default)]
+    pub split: SplitStrategy,
+    /// The seed of the generation; a random one is used if not set.
+    #[serde(default)]
+    pub seed: Option<u64>,
+    #[serde(default)]
+    pub operator_weights: OperatorWeights,
+    #[serde(default)]
+    pub generation: GenerationStrategy,
+}
+
+impl DatasetConfig {
+    /// Checks the settings that are invalid on their own or in combination.
+    pub fn validate(&self) -> Result<(), String> {
+        let weights = &self.operator_weights;
+        weights
+            .validate()
+            .map_err(|e| format!("invalid operator weights: {e}"))?;
+        if !(0.0..=1.0).contains(&self.const_probability) {
+            return Err("const_probability must be between 0 and 1".into());
+        }
+        if self.max_arity < 2 {
+            return Err("max_arity must be at least 2".into());
+        }
+
+        match self.generation {
+            GenerationStrategy::Recursive => {}
+            GenerationStrategy::ExactSize { size } => {
+                if let SplitStrategy::Depth { .. } = self.split {
+                    return Err(
+                        "the exact_size generation doesn't apply max_depth, which the depth \
+                         split relies on"
+                            .into(),
+                    );
+                }
+                if self.max_arity > 2 {
+                    return Err(
+                        "the exact_size generation only produces binary connectives, so \
+                         max_arity can't exceed 2"
+                            .into(),
+                    );
+                }
+                if weights.binary_total() == 0 {
+                    return Err(
+                        "the exact_size generation needs a binary connective with a positive \
+                         weight"
+                            .into(),
+                    );
+                }
+                if weights.expression_counts(size).is_none() {
+                    return Err(format!(
+                        "the number of expressions of size {size} overflows with these operator \
+                         weights; pick a smaller size or lower weights"
+                    ));
+                }
+            }
+            GenerationStrategy::ExactDepth => {
+                if weights.not == 0 && weights.binary_total() == 0 {
+                    return Err(
+                        "the exact_depth generation needs an operator with a positive weight"
+                            .into(),
+                    );
+                }
+            }
+            GenerationStrategy::DistinctVariables { count } => {
+                let max_vars = self.max_variables.min(self.variable_pool_size);
+                if !(1..=max_vars).contains(&count) {
+                    return Err(format!(
+                        "the number of distinct variables must be between 1 and {max_vars} \
+                         (max_variables and variable_pool_size)"
+                    ));
+                }
+                if count > self.max_variable_leaves() {
+                    return Err(format!(
+                        "the expressions can't have {count} variables with these max_depth, \
+                         max_arity, const_probability and operator weights"
+                    ));
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The largest number of variable occurrences the recursive generation can produce in the
+    /// expressions of every split.
+    fn max_variable_leaves(&self) -> usize {
+        if self.const_probability >= 1.0 {
+            return 0;
+        }
+
+        let weights = &self.operator_weights;
+        let arity = if weights.and > 0 || weights.or > 0 {
+            self.max_arity
+        } else if weights.binary_total() > 0 {
+            2
+        } else {
+            1
+        };
+        // the depth split generates the train and valid expressions with the smaller depth
+        let max_depth = match self.split {
+            SplitStrategy::Depth { max_train_depth } => max_train_depth,
+            _ => self.max_depth,
+        };
+
+        arity.saturating_pow(max_depth.try_into().unwrap_or(u32::MAX))
+    }
+}
+
+/// The kind of samples in the dataset, which determines their classes.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Task {
+    /// Evaluate an expression under the state preceding it.
+    #[default]
+    Evaluation,
+    /// Classify a state-free expression as a tautology, a contradiction or contingent.
+    Validity,
+    /// Classify a state-free expression as satisfiable or unsatisfiable.
+    Satisfiability,
+}
+
+impl Task {
+    /// The names of the classes, in the order of their labels.
+    pub fn classes(&self) -> &'static [&'static str] {
+        match self {
+            Task::Evaluation => &["false", "true"],
+            Task::Validity => &["contradiction", "contingent", "tautology"],
+            Task::Satisfiability => &["unsatisfiable", "satisfiable"],
+        }
+    }
+}
+
+/// The relative weights with which the generator picks each kind of node above the max depth;
+/// the weights that aren't specified keep their default values, and unknown ones are rejected.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct OperatorWeights {
+    pub var: u32,
+    pub not: u32,
+    pub and: u32,
+    pub or: u32,
+    #[serde(rename = "impl")]
+    pub implies: u32,
+    #[serde(rename = "equiv")]
+    pub equivalent: u32,
+    pub xor: u32,
+    pub nand: u32,
+    pub nor: u32,
+}
+
+impl Default for OperatorWeights {
+    fn default() -> Self {
+        Self {
+            var: 5,
+            not: 1,
+            and: 1,
+            or: 1,
+            implies: 3,
+            equivalent: 1,
+            xor: 1,
+            nand: 1,
+            nor: 1,
+        }
+    }
+}
+
+impl OperatorWeights {
+    /// Returns the weights along with their names.
+    pub fn entries(&self) -> [(&'static str, u32); 9] {
+        [
+            ("var", self.var),
+            ("not", self.not),
+            ("and", self.and),
+            ("or", self.or),
+            ("impl", self.implies),
+            ("equiv", self.equivalent),
+            ("xor", self.xor),
+            ("nand", self.nand),
+            ("nor", self.nor),
+        ]
+    }
+
+    /// Checks that the weights can be sampled from.
+    pub fn validate(&self) -> Result<(), String> {
+        let total = self
+            .entries()
+            .iter()
+            .try_fold(0u32, |total, (_, w)| total.checked_add(*w));
+        match total {
+            None => Err("the sum of the operator weights overflows".into()),
+            Some(0) => Err("at least one operator weight must be positive".into()),
+            Some(_) => Ok(()),
+        }
+    }
+
+    /// The sum of the weights of the binary connectives.
+    pub fn binary_total(&self) -> u32 {
+        self.entries()
+            .iter()
+            .filter(|(c, _)| !["var", "not"].contains(c))
+            .map(|(_, w)| w)
+            .sum()
+    }
+
+    /// Returns the number of expressions with each number of connectives up to `size`, in which
+    /// the leaves all count as the same and every negation and binary connective counts as many
+    /// times as its weight, or `None` if they overflow.
+    pub fn expression_counts(&self, size: usize) -> Option<Vec<u128>> {
+        let (not, binary) = (self.not as u128, self.binary_total() as u128);
+        let mut counts = vec![1u128];
+        for n in 1..=size {
+            let binary_count = (0..n).try_fold(0u128, |sum, l| {
+                counts[l]
+                    .checked_mul(counts[n - 1 - l])
+                    .and_then(|count| sum.checked_add(count))
+            })?;
+            let count = not
+                .checked_mul(counts[n - 1])?
+                .checked_add(binary.checked_mul(binary_count)?)?;
+            counts.push(count);
+        }
+
+        Some(counts)
+    }
+}
+
+/// How the generator picks the shapes of the expressions.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(tag = "strategy", rename_all = "snake_case")]
+pub enum GenerationStrategy {
+    /// Every node is picked according to the operator weights, down to `max_depth`.
+    #[default]
+    Recursive,
+    /// The expressions have exactly `size` connectives, and are picked uniformly among all the
+    /// expressions made of negations and binary connectives, each connective counting as many
+    /// times as its weight (the shapes are uniform if the `not` weight equals the sum of the binary
+    /// ones); `max_depth` doesn't apply, so it can't be combined with the `depth` split,
+    /// `max_arity` can't exceed 2, and the `var` weight is unused.
+    ExactSize { size: usize },
+    /// Like `recursive`, but the expressions always reach `max_depth`.
+    ExactDepth,
+    /// Like `recursive`, but the expressions have exactly `count` distinct variables.
+    DistinctVariables { count: usize },
+}
+
+/// How generated expressions are distributed between the train, valid and test splits.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(tag = "strategy", rename_all = "lowercase")]
+pub enum SplitStrategy {
+    /// Every split draws from the same distribution.
+    #[default]
+    Random,
+    /// The test split only contains expression structures that don't appear in training (nor,
+    /// if `disjoint_valid` is set, in the valid split).
+    Structure {
+        #[serde(default)]
+        disjoint_valid: bool,
+    },
+    /// The train and valid splits contain expressions of depth at most `max_train_depth`, and the
+    /// test split ones of depth `max_train_depth + 1`; overrides `max_depth`.
+    Depth { max_train_depth: usize },
+}
+
+/// The description of a generated dataset, stored in its `metadata` table as one row per field,
+/// with the values encoded as JSON.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct DatasetMetadata {
+    /// The version of mlml-dataset that generated the dataset.
+    pub version: String,
+    pub config: DatasetConfig,
+    /// The seed the dataset was generated with, which may differ from the configured one.
+    pub seed: u64,
+    /// The relative weights with which the generator picks each kind of node.
+    pub operator_weights: OperatorWeights,
+    /// The number of samples in each split.
+    pub counts: BTreeMap<String, usize>,
+    /// The number of samples with each expression structure, across all the splits.
+    pub structure_histogram: BTreeMap<String, usize>,
+    /// The number of tokens of the longest sample, as mlml-model's tokenizer encodes it.
+    pub max_sample_length: usize,
+    /// The number of candidates skipped for only differing from a sample by a renaming of their
+    /// variables; always 0 unless `dedup_alpha_equivalent` is set.
+    #[serde(default)]
+    pub alpha_collisions: usize,
+}
+
+impl DatasetMetadata {
+    /// Returns the (key, value) rows of the `metadata` table.
+    pub fn to_rows(&self) -> Vec<(String, String)> {
+        let serde_json::Value::Object(fields) = serde_json::to_value(self).unwrap() else {
+            unreachable!();
+        };
+
+        fields
+            .into_iter()
+            .map(|(key, value)| (key, value.to_string()))
+            .collect()
+    }
+
+    /// Reads the metadata back from the (key, value) rows of the `metadata` table.
+    pub fn from_rows(
+        rows: impl IntoIterator<Item = (String, String)>,
+    ) -> Result<Self, serde_json::Error> {
+        let mut fields = serde_json::Map::new();
+        for (key, value) in rows {
+            fields.insert(key, serde_json::from_str(&value)?);
+        }
+
+        serde_json::from_value(serde_json::Value::Object(fields))
+    }
+
+    /// Checks that a model configured with `config` can be trained on or evaluated with this
+    /// dataset.
+    pub fn check_compatibility(&self, config: &DatasetConfig) -> Result<(), String> {
+        if self.config.task != config.task {
+            return Err(format!(
+                "the dataset was generated for the {:?} task, but the {:?} task is configured",
+                self.config.task, config.task
+            ));
+        }
+        if self.config.max_variables > config.max_variables {
+            return Err(format!(
+                "the dataset contains up to {} variables per sample, but max_variables is {}",
+                self.config.max_variables, config.max_variables
+            ));
+        }
+        if self.max_sample_length > config.max_seq_length {
+            return Err(format!(
+                "the dataset contains samples of up to {} tokens, but max_seq_length is {}",
+                self.max_sample_length, config.max_seq_length
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+fn default_variable_pool_size() -> usize {
+    26
+}
+
+fn default_max_arity() -> usize {
+    2
 }
 
 #[allow(dead_code)]
 #[derive(Debug, Deserialize)]
 pub struct ModelConfig {
+    #[serde(default)]
+    pub architecture: Architecture,
     pub d_model: usize,
     pub d_ff: usize,
     pub n_heads: usize,
@@ -36,6 +405,61 @@ pub struct ModelConfig {
     pub dropout: f64,
     pub weight_decay: f32,
     pub gradient_clipping_norm: f32,
+    #[serde(default)]
+    pub pooling: Pooling,
+    #[serde(default)]
+    pub position_encoding: PositionEncoding,
+    /// Whether the embeddings of the tokens include their coordinates in the expression's parse
+    /// tree.
+    #[serde(default)]
+    pub tree_positions: bool,
+}
+
+/// The kind of model to train.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Architecture {
+    /// A transformer encoder over the tokenized samples.
+    #[default]
+    Transformer,
+    /// A Tree-LSTM that composes the embeddings of the nodes of the parse tree bottom-up; only
+    /// `d_model` applies to it.
+    TreeLstm,
+    /// A graph neural network that passes messages through the expression's DAG, in which the
+    /// occurrences of each variable share a node; `d_model`, `n_layers` and `dropout` apply to it.
+    Gnn,
+}
+
+/// How the model is told the positions of the tokens.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PositionEncoding {
+    /// A learned embedding per position, added to the token embeddings; limits the inputs to
+    /// `max_seq_length` tokens.
+    #[default]
+    Learned,
+    /// Fixed sinusoids added to the token embeddings.
+    Sinusoidal,
+    /// Rotary position embeddings (RoPE), which rotate the attention queries and keys.
+    Rotary,
+    /// Attention with linear biases (ALiBi), which penalise the attention scores proportionally to
+    /// the distance between the tokens.
+    Alibi,
+}
+
+/// How the encoder outputs of a sequence are reduced to the vector it is classified from.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Pooling {
+    /// The output at the `<cls>` token that starts every sequence.
+    #[default]
+    Cls,
+    /// The mean of the outputs at the non-padding positions.
+    Mean,
+    /// The elementwise max of the outputs at the non-padding positions.
+    Max,
+    /// A weighted sum of the outputs at the non-padding positions, with learned weights.
+    Attention,
 }
 
 #[allow(dead_code)]
@@ -64,3 +488,156 @@ pub fn config_path() -> PathBuf {
 
     config_path
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dataset_config(json: &str) -> DatasetConfig {
+        serde_json::from_str(json).unwrap()
+    }
+
+    #[test]
+    fn metadata_round_trip() {
+        let config = dataset_config(
+            r#"{
+                "task": "validity",
+                "train_samples_count": 10,
+                "valid_samples_count": 2,
+                "test_samples_count": 2,
+                "max_seq_length": 100,
+                "max_variables": 5,
+                "max_depth": 2,
+                "db_path": "/tmp/dataset.db"
+            }"#,
+        );
+        let metadata = DatasetMetadata {
+            version: "0.1.0".into(),
+            config: config.clone(),
+            seed: 7,
+            operator_weights: OperatorWeights::default(),
+            counts: [("train".into(), 10)].into(),
+            structure_histogram: [("_ → _".into(), 3)].into(),
+            max_sample_length: 40,
+            alpha_collisions: 2,
+        };
+
+        let read = DatasetMetadata::from_rows(metadata.to_rows()).unwrap();
+        assert_eq!(read.seed, 7);
+        assert_eq!(read.structure_histogram, metadata.structure_histogram);
+        assert_eq!(read.alpha_collisions, 2);
+        assert_eq!(read.config.task, Task::Validity);
+        assert!(read.check_compatibility(&config).is_ok());
+
+        let mut smaller = config.clone();
+        smaller.max_variables -= 1;
+        assert!(read.check_compatibility(&smaller).is_err());
+
+        let mut shorter = config.clone();
+        shorter.max_seq_length = 39;
+        assert!(read.check_compatibility(&shorter).is_err());
+
+        let mut other_task = config;
+        other_task.task = Task::Evaluation;
+        assert!(read.check_compatibility(&other_task).is_err());
+    }
+
+    #[test]
+    fn validation() {
+        let mut config = dataset_config(
+            r#"{
+                "train_samples_count": 10,
+                "valid_samples_count": 2,
+                "test_samples_count": 2,
+                "max_seq_length": 100,
+                "max_variables": 5,
+                "max_depth": 2,
+                "db_path": "/tmp/dataset.db",
+                "generation": { "strategy": "exact_size", "size": 6 }
+            }"#,
+        );
+        assert!(config.validate().is_ok());
+
+        config.split = SplitStrategy::Depth { max_train_depth: 2 };
+        assert!(config.validate().is_err());
+
+        config.split = SplitStrategy::Random;
+        config.max_arity = 3;
+        assert!(config.validate().is_err());
+
+        config.max_arity = 2;
+        config.operator_weights.var = u32::MAX;
+        assert!(config.validate().is_err());
+
+        // the settings the generator depends on are checked whatever the strategy
+        config.operator_weights = OperatorWeights::default();
+        config.generation = GenerationStrategy::Recursive;
+        config.max_arity = 1;
+        assert!(config.validate().is_err());
+        config.max_arity = 2;
+        config.const_probability = 1.5;
+        assert!(config.validate().is_err());
+        config.const_probability = 0.0;
+
+        config.generation = GenerationStrategy::ExactSize { size: 200 };
+        assert!(config.validate().is_err());
+        config.generation = GenerationStrategy::ExactSize { size: 6 };
+        config.operator_weights = OperatorWeights {
+            and: 0,
+            or: 0,
+            implies: 0,
+            equivalent: 0,
+            xor: 0,
+            nand: 0,
+            nor: 0,
+            ..Default::default()
+        };
+        assert!(config.validate().is_err());
+        config.generation = GenerationStrategy::ExactDepth;
+        assert!(config.validate().is_ok());
+        config.operator_weights.not = 0;
+        assert!(config.validate().is_err());
+
+        // expressions of depth 2 have at most 4 variables, or just 1 without binary connectives
+        config.operator_weights = OperatorWeights::default();
+        config.generation = GenerationStrategy::DistinctVariables { count: 4 };
+        assert!(config.validate().is_ok());
+        config.generation = GenerationStrategy::DistinctVariables { count: 5 };
+        assert!(config.validate().is_err());
+        config.max_arity = 3;
+        assert!(config.validate().is_ok());
+        config.generation = GenerationStrategy::DistinctVariables { count: 6 };
+        assert!(config.validate().is_err());
+        config.generation = GenerationStrategy::DistinctVariables { count: 2 };
+        config.operator_weights.and = 0;
+        config.operator_weights.or = 0;
+        config.split = SplitStrategy::Depth { max_train_depth: 1 };
+        assert!(config.validate().is_ok());
+        config.generation = GenerationStrategy::DistinctVariables { count: 3 };
+        assert!(config.validate().is_err());
+        config.split = SplitStrategy::Random;
+        config.const_probability = 1.0;
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn operator_weights() {
+        let weights: OperatorWeights = serde_json::from_str(r#"{ "impl": 0, "xor": 4 }"#).unwrap();
+        assert_eq!(weights.implies, 0);
+        assert_eq!(weights.xor, 4);
+        assert_eq!(weights.var, OperatorWeights::default().var);
+        assert!(weights.validate().is_ok());
+        assert!(serde_json::from_str::<OperatorWeights>(r#"{ "implies": 3 }"#).is_err());
+
+        let zero = serde_json::from_str::<OperatorWeights>(
+            r#"{ "var": 0, "not": 0, "and": 0, "or": 0, "impl": 0, "equiv": 0, "xor": 0, "nand": 0, "nor": 0 }"#,
+        )
+        .unwrap();
+        assert!(zero.validate().is_err());
+        let huge = OperatorWeights {
+            var: u32::MAX,
+            ..Default::default()
+        };
+        assert!(huge.validate().is_err());
+    }
+}